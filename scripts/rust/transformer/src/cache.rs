@@ -0,0 +1,91 @@
+use serde::{ Deserialize, Serialize };
+use std::{ collections::BTreeMap, fs, io, path::Path };
+
+use crate::{ diagnostics::Diagnostic, Lookup, Rewrite, SourcemapData };
+
+/// Bumped whenever the layout of the cache file changes, so stale caches are discarded.
+const CACHE_VERSION: u32 = 9;
+
+/// 64-bit FNV-1a. Only used to detect changes, and unlike `DefaultHasher` it is guaranteed to be
+/// stable across Rust releases, which matters for a cache that lives on disk.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Hashes what each lookup currently resolves to, so a file is only considered stale when the
+/// part of the sourcemap it actually depends on has changed.
+pub fn hash_lookups(lookups: &[Lookup], sourcemap_data: &SourcemapData) -> String {
+    let resolved: Vec<Option<String>> = lookups
+        .iter()
+        .map(|lookup| lookup.resolve(sourcemap_data))
+        .collect();
+    hash_bytes(serde_json::to_string(&resolved).expect("Failed to serialize lookups").as_bytes())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+    pub input_hash: String,
+    pub output_hash: String,
+    pub lookups: Vec<Lookup>,
+    pub lookups_hash: String,
+    pub diagnostics: Vec<Diagnostic>,
+    pub rewrites: Vec<Rewrite>,
+}
+
+impl CacheEntry {
+    /// `input_hash` is the hash of the source file as it is now; `output_path` is where the
+    /// transformed file was written, which is the source file itself for in-place runs.
+    pub fn is_fresh(
+        &self,
+        input_hash: &str,
+        output_path: &Path,
+        sourcemap_data: &SourcemapData
+    ) -> bool {
+        self.input_hash == input_hash &&
+            fs
+                ::read(output_path)
+                .is_ok_and(|output| hash_bytes(&output) == self.output_hash) &&
+            hash_lookups(&self.lookups, sourcemap_data) == self.lookups_hash
+    }
+}
+
+/// The on-disk cache used for incremental runs, keyed by path relative to the transform
+/// directory.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Cache {
+    version: u32,
+    /// Hash of everything outside the individual files that affects the output, such as the
+    /// runtime path and the emission settings.
+    fingerprint: String,
+    pub files: BTreeMap<String, CacheEntry>,
+}
+
+impl Cache {
+    pub fn new(fingerprint: String) -> Cache {
+        Cache { version: CACHE_VERSION, fingerprint, files: BTreeMap::new() }
+    }
+
+    /// Returns an empty cache when the file is missing, unreadable or was written with different
+    /// settings, since any of those means nothing in it can be trusted.
+    pub fn load(path: &Path, fingerprint: &str) -> Cache {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Cache>(&content).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.fingerprint == fingerprint)
+            .unwrap_or_else(|| Cache::new(fingerprint.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self).expect("Failed to serialize cache"))
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+
+use crate::{
+    config::{ DiagnosticsLevel, EmitStyle, ExtraSourcemap },
+    diagnostics::DiagnosticsFormat,
+};
+
+pub const USAGE: &str =
+    "[watch | lint-sourcemap] [options] [<transform_directory> [<path/to/sourcemap.json> [<path/to/runtime.luau>]]]
+
+The sourcemap may also be a Rojo *.project.json, which is then read directly instead of the
+output of `rojo sourcemap`. Further sourcemaps can be merged in with --merge-sourcemap, so
+imports between several Rojo projects are resolved in one run.
+
+Commands:
+    watch                    Transform once, then again whenever files or the sourcemap change
+    lint-sourcemap           Report sibling names FindFirstChild cannot tell apart, names that do
+                             not survive emission and files missing from the sourcemap; no runtime
+                             is needed and nothing is transformed
+
+Options:
+    --config <file>          Read settings from <file> instead of a discovered transformer.toml
+    --dry-run                Print a unified diff of every change instead of writing files
+    --check                  Report imports that cannot be resolved and exit non-zero if any
+    --strict-sourcemap       Treat sourcemap entries whose files are missing as errors
+    --merge-sourcemap <file>[=<mount>]
+                             Also resolve against <file>, with its root placed at <mount>, e.g.
+                             ReplicatedStorage/Shared, or merged into the DataModel (repeatable)
+    --out-dir <directory>    Write the transformed tree to <directory> instead of in place
+    --report <file.json>     Write every rewrite performed to <file.json>
+    --cache <file>           Skip files whose inputs are unchanged since the run that wrote <file>
+    -j, --jobs <n>           Number of files processed in parallel (default: one per core)
+    --include <glob>         Only transform files matching <glob> (repeatable)
+    --exclude <glob>         Skip files matching <glob> (repeatable)
+    --emit <style>           Require emission style: find-first-child, wait-for-child,
+                             bracket-index, dot-index or string-require
+    --wait-for-child-timeout <seconds>
+                             Pass a timeout to every emitted WaitForChild
+    --get-service-above <n>  Start requires that climb more than <n> parents from
+                             game:GetService(...) instead of script
+    --get-service-across-services
+                             Start requires of modules in another service from
+                             game:GetService(...) instead of script
+    --hoist-requires         Bind requires emitted more than once in a file to a single local
+    --diagnostics <level>    Minimum level to print: error, warning or note
+    --poll                   Poll for changes in watch mode instead of using native file events
+    --diagnostics-format <format>
+                             Print diagnostics as human readable text or JSON lines: human, json";
+
+/// Command line arguments. Everything is optional here because any missing value may still be
+/// provided by the configuration file.
+#[derive(Default)]
+pub struct Options {
+    pub transform_directory: Option<PathBuf>,
+    pub sourcemap: Option<PathBuf>,
+    pub extra_sourcemaps: Vec<ExtraSourcemap>,
+    pub runtime: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub watch: bool,
+    pub lint_sourcemap: bool,
+    pub poll: bool,
+    pub dry_run: bool,
+    pub check: bool,
+    pub strict_sourcemap: bool,
+    pub out_dir: Option<PathBuf>,
+    pub report: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+    pub jobs: Option<usize>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub emit_style: Option<EmitStyle>,
+    pub wait_for_child_timeout: Option<f64>,
+    pub get_service_max_parents: Option<usize>,
+    pub get_service_across_services: bool,
+    pub hoist_requires: bool,
+    pub diagnostics_level: Option<DiagnosticsLevel>,
+    pub diagnostics_format: Option<DiagnosticsFormat>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter().peekable();
+    match args.peek().map(|arg| arg.as_str()) {
+        Some("watch") => {
+            options.watch = true;
+            args.next();
+        }
+        Some("lint-sourcemap") => {
+            options.lint_sourcemap = true;
+            args.next();
+        }
+        _ => {}
+    }
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next().cloned().ok_or_else(|| format!("Missing value for {}", arg))
+        };
+
+        match arg.as_str() {
+            "--config" => {
+                options.config = Some(PathBuf::from(value()?));
+            }
+            "--dry-run" => {
+                options.dry_run = true;
+            }
+            "--check" => {
+                options.check = true;
+            }
+            "--strict-sourcemap" => {
+                options.strict_sourcemap = true;
+            }
+            "--merge-sourcemap" => {
+                options.extra_sourcemaps.push(value()?.parse()?);
+            }
+            "--out-dir" => {
+                options.out_dir = Some(PathBuf::from(value()?));
+            }
+            "--report" => {
+                options.report = Some(PathBuf::from(value()?));
+            }
+            "--cache" => {
+                options.cache = Some(PathBuf::from(value()?));
+            }
+            "--jobs" | "-j" => {
+                let jobs = value()?;
+                options.jobs = Some(
+                    jobs.parse().map_err(|_| format!("Invalid number of jobs: {}", jobs))?
+                );
+            }
+            "--include" => {
+                options.include.push(value()?);
+            }
+            "--exclude" => {
+                options.exclude.push(value()?);
+            }
+            "--emit" => {
+                options.emit_style = Some(value()?.parse()?);
+            }
+            "--wait-for-child-timeout" => {
+                let timeout = value()?;
+                options.wait_for_child_timeout = Some(
+                    timeout.parse().map_err(|_| format!("Invalid timeout: {}", timeout))?
+                );
+            }
+            "--get-service-above" => {
+                let parents = value()?;
+                options.get_service_max_parents = Some(
+                    parents.parse().map_err(|_| format!("Invalid number of parents: {}", parents))?
+                );
+            }
+            "--get-service-across-services" => {
+                options.get_service_across_services = true;
+            }
+            "--hoist-requires" => {
+                options.hoist_requires = true;
+            }
+            "--diagnostics" => {
+                options.diagnostics_level = Some(value()?.parse()?);
+            }
+            "--poll" => {
+                options.poll = true;
+            }
+            "--diagnostics-format" => {
+                options.diagnostics_format = Some(value()?.parse()?);
+            }
+            flag if flag.starts_with("--") => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.transform_directory = positional.next();
+    options.sourcemap = positional.next();
+    options.runtime = positional.next();
+
+    if let Some(extra) = positional.next() {
+        return Err(format!("Unexpected argument: {}", extra.display()));
+    }
+
+    Ok(options)
+}
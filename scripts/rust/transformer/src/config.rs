@@ -0,0 +1,370 @@
+use globset::{ GlobBuilder, GlobSet, GlobSetBuilder };
+use serde::Deserialize;
+use std::{ collections::BTreeMap, fs, path::{ Path, PathBuf }, str::FromStr, thread };
+
+use crate::{ cli::Options, diagnostics::DiagnosticsFormat };
+
+pub const CONFIG_FILE_NAME: &str = "transformer.toml";
+
+/// How resolved Roblox paths are turned into `require(...)` arguments.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmitStyle {
+    /// `script.Parent:FindFirstChild("a"):FindFirstChild("b")`
+    #[default]
+    FindFirstChild,
+    /// `script.Parent:WaitForChild("a"):WaitForChild("b")`, for code that runs on clients before
+    /// replication has finished
+    WaitForChild,
+    /// `require("./a/b")`, Luau's require-by-string relative to the requiring file, using the
+    /// aliases of `.luaurc` files
+    StringRequire,
+    /// `script["Parent"]["a"]["b"]`, for code that does not need to survive missing instances
+    BracketIndex,
+    /// `script.Parent.a.b`, falling back to brackets for names that are not identifiers
+    DotIndex,
+}
+
+impl FromStr for EmitStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "find-first-child" => Ok(EmitStyle::FindFirstChild),
+            "wait-for-child" => Ok(EmitStyle::WaitForChild),
+            "string-require" => Ok(EmitStyle::StringRequire),
+            "bracket-index" => Ok(EmitStyle::BracketIndex),
+            "dot-index" => Ok(EmitStyle::DotIndex),
+            _ => Err(format!("Unknown emission style: {}", value)),
+        }
+    }
+}
+
+/// The least severe kind of message that is still printed.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticsLevel {
+    Error,
+    Warning,
+    #[default]
+    Note,
+}
+
+impl FromStr for DiagnosticsLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(DiagnosticsLevel::Error),
+            "warning" => Ok(DiagnosticsLevel::Warning),
+            "note" => Ok(DiagnosticsLevel::Note),
+            _ => Err(format!("Unknown diagnostics level: {}", value)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EmitConfig {
+    pub style: Option<EmitStyle>,
+    pub wait_for_child: WaitForChildConfig,
+    pub get_service: GetServiceConfig,
+    pub hoist_requires: bool,
+}
+
+/// When instance requires start from `game:GetService("...")` instead of climbing from
+/// `script`, so they keep working when the script is moved.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GetServiceConfig {
+    /// Requires that would need more `.Parent` steps than this.
+    pub max_parents: Option<usize>,
+    /// Requires of modules in another service than the requiring script.
+    pub across_services: bool,
+}
+
+/// Where `WaitForChild` is emitted even though the style is `find-first-child`, and how long it
+/// waits.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WaitForChildConfig {
+    /// Scripts below these instances, written as `/`-separated names starting below the
+    /// DataModel, e.g. `StarterPlayer/StarterPlayerScripts`.
+    pub paths: Vec<String>,
+    /// Scripts of these classes, e.g. `LocalScript`.
+    pub script_kinds: Vec<String>,
+    /// Seconds passed as the second argument of every `WaitForChild`; without it they wait
+    /// forever.
+    pub timeout: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DiagnosticsConfig {
+    pub level: Option<DiagnosticsLevel>,
+    pub format: Option<DiagnosticsFormat>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackagesConfig {
+    /// Package names such as `@rbxts/t` mapped to the directory of the copy to use when the
+    /// sourcemap contains several.
+    pub pin: BTreeMap<String, PathBuf>,
+}
+
+/// A sourcemap merged into the main one, so imports can cross into another Rojo project.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ExtraSourcemap {
+    pub path: PathBuf,
+    /// Directory the file paths in the sourcemap are relative to, by default the one containing it.
+    pub base_dir: Option<PathBuf>,
+    /// Names of the instances, starting below the DataModel, the root of the sourcemap is placed
+    /// at. Without one its root is merged into the DataModel of the main sourcemap.
+    #[serde(default)]
+    pub mount: Vec<String>,
+}
+
+impl FromStr for ExtraSourcemap {
+    type Err = String;
+
+    /// Parses `<file>` or `<file>=<mount>`, where the mount separates instance names with `/`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (path, mount) = match value.split_once('=') {
+            Some((path, mount)) => (path, mount.split('/').map(String::from).collect()),
+            None => (value, Vec::new()),
+        };
+        if path.is_empty() {
+            return Err(format!("Missing sourcemap path in {}", value));
+        }
+        if mount.iter().any(String::is_empty) {
+            return Err(format!("Invalid mount point in {}", value));
+        }
+        Ok(ExtraSourcemap { path: PathBuf::from(path), base_dir: None, mount })
+    }
+}
+
+/// The contents of a `transformer.toml`. Relative paths are resolved against the directory the
+/// file lives in.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub transform_directory: Option<PathBuf>,
+    pub sourcemap: Option<PathBuf>,
+    pub extra_sourcemaps: Vec<ExtraSourcemap>,
+    pub runtime: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    pub report: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub extensions: Option<Vec<String>>,
+    pub prefilter: Option<String>,
+    pub parser_stack_mb: Option<usize>,
+    pub jobs: Option<usize>,
+    pub strict_sourcemap: bool,
+    pub emit: EmitConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub packages: PackagesConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let content = fs
+            ::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let mut config: Config = toml
+            ::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+
+        let config_dir = path.parent().unwrap_or(Path::new("."));
+        for path in [
+            &mut config.transform_directory,
+            &mut config.sourcemap,
+            &mut config.runtime,
+            &mut config.out_dir,
+            &mut config.report,
+            &mut config.cache,
+        ]
+            .into_iter()
+            .flatten() {
+            *path = config_dir.join(&*path);
+        }
+        for path in config.packages.pin.values_mut() {
+            *path = config_dir.join(&*path);
+        }
+        for extra in &mut config.extra_sourcemaps {
+            extra.path = config_dir.join(&extra.path);
+            if let Some(base_dir) = &mut extra.base_dir {
+                *base_dir = config_dir.join(&*base_dir);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Decides which files under the transform directory are parsed and rewritten.
+pub struct FileFilter {
+    extensions: Vec<String>,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl FileFilter {
+    /// `relative_path` is relative to the transform directory.
+    pub fn matches(&self, relative_path: &Path) -> bool {
+        let has_extension = relative_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+
+        has_extension &&
+            self.include.as_ref().is_none_or(|include| include.is_match(relative_path)) &&
+            !self.exclude.is_match(relative_path)
+    }
+}
+
+pub fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| format!("Invalid glob {}: {}", pattern, err))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+/// Fully resolved settings for a run, after merging the configuration file with the command line.
+pub struct Settings {
+    pub transform_directory: PathBuf,
+    pub sourcemap: PathBuf,
+    pub extra_sourcemaps: Vec<ExtraSourcemap>,
+    pub runtime: PathBuf,
+    pub out_dir: Option<PathBuf>,
+    pub report: Option<PathBuf>,
+    pub cache: Option<PathBuf>,
+    pub watch: bool,
+    pub lint_sourcemap: bool,
+    pub poll: bool,
+    pub dry_run: bool,
+    pub check: bool,
+    /// Sourcemap entries whose files are missing, and imports of them, are errors rather than
+    /// warnings.
+    pub strict_sourcemap: bool,
+    pub file_filter: FileFilter,
+    pub prefilter: String,
+    pub parser_stack_size: usize,
+    pub jobs: usize,
+    pub emit_style: EmitStyle,
+    pub wait_for_child: WaitForChildConfig,
+    pub get_service: GetServiceConfig,
+    /// Requires emitted more than once in a file are bound to a single local at its top.
+    pub hoist_requires: bool,
+    /// Canonicalized, so they can be compared with the paths in the sourcemap.
+    pub package_pins: BTreeMap<String, PathBuf>,
+    pub diagnostics_level: DiagnosticsLevel,
+    pub diagnostics_format: DiagnosticsFormat,
+}
+
+impl Settings {
+    /// Looks for the configuration file given with `--config`, otherwise next to the sourcemap
+    /// given on the command line, otherwise in the working directory.
+    pub fn resolve(options: Options) -> Result<Settings, String> {
+        let config_path = match &options.config {
+            Some(path) => Some(path.clone()),
+            None => {
+                let search_dir = options.sourcemap
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .unwrap_or(Path::new(""));
+                Some(search_dir.join(CONFIG_FILE_NAME)).filter(|path| path.is_file())
+            }
+        };
+        let config = match &config_path {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let missing = |name: &str| {
+            format!("No {} given on the command line or in {}", name, CONFIG_FILE_NAME)
+        };
+        let include = if options.include.is_empty() { config.include } else { options.include };
+        let exclude = if options.exclude.is_empty() { config.exclude } else { options.exclude };
+        let mut wait_for_child = config.emit.wait_for_child;
+        wait_for_child.timeout = options.wait_for_child_timeout.or(wait_for_child.timeout);
+        if wait_for_child.timeout.is_some_and(|timeout| !timeout.is_finite() || timeout <= 0.0) {
+            return Err("wait-for-child timeout must be a positive number of seconds".into());
+        }
+        let mut get_service = config.emit.get_service;
+        get_service.max_parents = options.get_service_max_parents.or(get_service.max_parents);
+        get_service.across_services |= options.get_service_across_services;
+        let extra_sourcemaps = if options.extra_sourcemaps.is_empty() {
+            config.extra_sourcemaps
+        } else {
+            options.extra_sourcemaps
+        };
+
+        Ok(Settings {
+            transform_directory: options.transform_directory
+                .or(config.transform_directory)
+                .ok_or_else(|| missing("transform directory"))?,
+            sourcemap: options.sourcemap
+                .or(config.sourcemap)
+                .ok_or_else(|| missing("sourcemap"))?,
+            extra_sourcemaps,
+            // `lint-sourcemap` never loads the runtime, so it does not have to be given
+            runtime: options.runtime
+                .or(config.runtime)
+                .or_else(|| options.lint_sourcemap.then(PathBuf::new))
+                .ok_or_else(|| missing("runtime"))?,
+            out_dir: options.out_dir.or(config.out_dir),
+            report: options.report.or(config.report),
+            cache: options.cache.or(config.cache),
+            watch: options.watch,
+            lint_sourcemap: options.lint_sourcemap,
+            poll: options.poll,
+            dry_run: options.dry_run,
+            check: options.check,
+            strict_sourcemap: options.strict_sourcemap || config.strict_sourcemap,
+            file_filter: FileFilter {
+                extensions: config.extensions.unwrap_or_else(|| vec!["luau".into(), "lua".into()]),
+                include: if include.is_empty() { None } else { Some(build_glob_set(&include)?) },
+                exclude: build_glob_set(&exclude)?,
+            },
+            prefilter: config.prefilter.unwrap_or_else(|| "local TS = _G[script]".into()),
+            parser_stack_size: config.parser_stack_mb.unwrap_or(8) * 1024 * 1024,
+            jobs: match options.jobs.or(config.jobs) {
+                Some(0) => {
+                    return Err("jobs must be at least 1".into());
+                }
+                Some(jobs) => jobs,
+                None => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            },
+            emit_style: options.emit_style.or(config.emit.style).unwrap_or_default(),
+            wait_for_child,
+            get_service,
+            hoist_requires: options.hoist_requires || config.emit.hoist_requires,
+            package_pins: config.packages.pin
+                .into_iter()
+                .map(|(package, path)| {
+                    dunce
+                        ::canonicalize(&path)
+                        .map(|path| (package.clone(), path))
+                        .map_err(|err| {
+                            format!("Pinned path for {} {}: {}", package, path.display(), err)
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+            diagnostics_level: options.diagnostics_level
+                .or(config.diagnostics.level)
+                .unwrap_or_default(),
+            diagnostics_format: options.diagnostics_format
+                .or(config.diagnostics.format)
+                .unwrap_or_default(),
+        })
+    }
+}
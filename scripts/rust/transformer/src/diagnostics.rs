@@ -0,0 +1,326 @@
+use full_moon::{ node::Node, tokenizer::Position };
+use serde::{ Deserialize, Deserializer, Serialize, Serializer, de };
+use std::{ fmt, path::{ Path, PathBuf }, str::FromStr };
+
+use crate::config::DiagnosticsLevel;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn of_start(node: &impl Node) -> Location {
+        Location::from(node.start_position())
+    }
+
+    pub fn of_end(node: &impl Node) -> Location {
+        Location::from(node.end_position())
+    }
+}
+
+impl From<Option<Position>> for Location {
+    fn from(position: Option<Position>) -> Self {
+        position
+            .map(|position| Location { line: position.line(), column: position.character() })
+            .unwrap_or(Location { line: 0, column: 0 })
+    }
+}
+
+impl From<Position> for Location {
+    fn from(position: Position) -> Self {
+        Location::from(Some(position))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+
+impl Span {
+    pub fn of(node: &impl Node) -> Span {
+        Span { start: Location::of_start(node), end: Location::of_end(node) }
+    }
+
+    pub fn between(start: &impl Node, end: &impl Node) -> Span {
+        Span { start: Location::of_start(start), end: Location::of_end(end) }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn is_shown_at(self, level: DiagnosticsLevel) -> bool {
+        match self {
+            Severity::Error => true,
+            Severity::Warning => level >= DiagnosticsLevel::Warning,
+            Severity::Note => level >= DiagnosticsLevel::Note,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// Stable identifiers for every kind of diagnostic. Codes are never reused or renumbered, so
+/// tooling can match on them across releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    /// A path given on the command line or in the configuration does not exist.
+    InvalidInput,
+    /// The sourcemap file could not be read.
+    SourcemapRead,
+    /// The sourcemap file is not valid sourcemap JSON.
+    SourcemapParse,
+    /// A sourcemap entry points at a file that does not exist.
+    SourcemapPathMissing,
+    /// A file under the transform directory could not be read.
+    FileRead,
+    /// A transformed or copied file could not be written.
+    FileWrite,
+    /// A file failed to parse as Luau and was left untouched.
+    ParseError,
+    /// A `TS.import` could not be resolved against the sourcemap.
+    UnresolvedImport,
+    /// `_G[script]` could not be replaced because the runtime or the script is not in the sourcemap.
+    UnresolvedRuntime,
+    /// An import resolves to an instance that is not a ModuleScript, so `require` would fail.
+    NotAModule,
+    /// A package matches several instances in the sourcemap and none of them is pinned.
+    AmbiguousPackage,
+    /// A relative import reaches into another package instead of going through `TS.getModule`.
+    PackageInternals,
+    /// An import resolves to an instance whose file is listed in the sourcemap but missing on disk.
+    MissingFile,
+    /// Siblings in the sourcemap share a name, so `FindFirstChild` may return either of them.
+    DuplicateName,
+    /// An instance name cannot be reproduced reliably in an emitted `FindFirstChild` call.
+    UnsafeName,
+    /// A script under the transform directory does not appear in the sourcemap.
+    UnmappedFile,
+    /// A string require was asked for, but the import resolves to an instance without a file.
+    NoStringRequire,
+}
+
+impl Code {
+    const ALL: [Code; 17] = [
+        Code::InvalidInput,
+        Code::SourcemapRead,
+        Code::SourcemapParse,
+        Code::SourcemapPathMissing,
+        Code::FileRead,
+        Code::FileWrite,
+        Code::ParseError,
+        Code::UnresolvedImport,
+        Code::UnresolvedRuntime,
+        Code::NotAModule,
+        Code::AmbiguousPackage,
+        Code::PackageInternals,
+        Code::MissingFile,
+        Code::DuplicateName,
+        Code::UnsafeName,
+        Code::UnmappedFile,
+        Code::NoStringRequire,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::InvalidInput => "T001",
+            Code::SourcemapRead => "T002",
+            Code::SourcemapParse => "T003",
+            Code::SourcemapPathMissing => "T004",
+            Code::FileRead => "T005",
+            Code::FileWrite => "T006",
+            Code::ParseError => "T007",
+            Code::UnresolvedImport => "T008",
+            Code::UnresolvedRuntime => "T009",
+            Code::NotAModule => "T010",
+            Code::AmbiguousPackage => "T011",
+            Code::PackageInternals => "T012",
+            Code::MissingFile => "T013",
+            Code::DuplicateName => "T014",
+            Code::UnsafeName => "T015",
+            Code::UnmappedFile => "T016",
+            Code::NoStringRequire => "T017",
+        }
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Code::ALL.into_iter()
+            .find(|candidate| candidate.as_str() == code)
+            .ok_or_else(|| de::Error::custom(format!("unknown diagnostic code {}", code)))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<Span>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: Code, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            file: None,
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(code: Code, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: Code, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_file(mut self, file: &Path) -> Diagnostic {
+        self.file = Some(file.to_path_buf());
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders in the `error[T008]: message` / `  --> file:line:col` layout used by rustc.
+    pub fn render_human(&self) -> String {
+        let mut rendered = format!("{}[{}]: {}", self.severity, self.code.as_str(), self.message);
+        if let Some(file) = &self.file {
+            rendered.push_str(&format!("\n  --> {}", file.display()));
+            if let Some(span) = &self.span {
+                rendered.push_str(&format!(":{}:{}", span.start.line, span.start.column));
+            }
+        }
+        for note in &self.notes {
+            rendered.push_str(&format!("\n   = note: {}", note));
+        }
+        rendered
+    }
+
+    /// Renders as a single line of JSON so the output can be consumed as JSON Lines.
+    pub fn render_json(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize diagnostic")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticsFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl FromStr for DiagnosticsFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "human" => Ok(DiagnosticsFormat::Human),
+            "json" => Ok(DiagnosticsFormat::Json),
+            _ => Err(format!("Unknown diagnostics format: {}", value)),
+        }
+    }
+}
+
+/// Prints diagnostics to stderr as they are emitted and keeps count of them for the final summary
+/// and exit code.
+pub struct Emitter {
+    format: DiagnosticsFormat,
+    level: DiagnosticsLevel,
+    errors: usize,
+    warnings: usize,
+}
+
+impl Emitter {
+    pub fn new(format: DiagnosticsFormat, level: DiagnosticsLevel) -> Emitter {
+        Emitter { format, level, errors: 0, warnings: 0 }
+    }
+
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Error => {
+                self.errors += 1;
+            }
+            Severity::Warning => {
+                self.warnings += 1;
+            }
+            Severity::Note => {}
+        }
+
+        if !diagnostic.severity.is_shown_at(self.level) {
+            return;
+        }
+
+        match self.format {
+            DiagnosticsFormat::Human => eprintln!("{}", diagnostic.render_human()),
+            DiagnosticsFormat::Json => eprintln!("{}", diagnostic.render_json()),
+        }
+    }
+
+    pub fn emit_all(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        for diagnostic in diagnostics {
+            self.emit(diagnostic);
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+
+    /// Prints the closing summary and returns the process exit code.
+    pub fn finish(&self) -> i32 {
+        if self.format == DiagnosticsFormat::Human && (self.errors > 0 || self.warnings > 0) {
+            eprintln!(
+                "Finished with {} error(s) and {} warning(s).",
+                self.errors,
+                self.warnings
+            );
+        }
+
+        if self.has_errors() { 1 } else { 0 }
+    }
+}
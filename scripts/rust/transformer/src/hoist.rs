@@ -0,0 +1,342 @@
+use full_moon::{
+    ast::{
+        Ast,
+        Call,
+        Expression,
+        FunctionArgs,
+        FunctionCall,
+        Index,
+        Prefix,
+        Stmt,
+        Suffix,
+        Var,
+        VarExpression,
+    },
+    parse_fallible,
+    tokenizer::{ Token, TokenReference, TokenType },
+    visitors::{ Visit, Visitor, VisitorMut },
+    LuaVersion,
+};
+use std::collections::{ HashMap, HashSet };
+
+/// A require emitted by the transformer, which may be bound to a local.
+#[derive(Debug, Clone)]
+pub struct EmittedRequire {
+    /// The module it loads; requires of the same module may be written differently.
+    pub target: String,
+    /// What a local holding the module is named after.
+    pub name: String,
+}
+
+/// Binds every module required more than once in a file to a single local, declared right
+/// before the statement that first requires it, and replaces each require with that local.
+/// `requires` maps the emitted requires, as code, to what they load.
+///
+/// Only modules first required by the `local` statements at the top of the file, where roblox-ts
+/// puts its imports, are bound. Modules therefore still run in the same order, and requires
+/// inside functions are never moved out of them.
+pub fn hoist_requires(ast: Ast, requires: &HashMap<String, EmittedRequire>) -> Ast {
+    let mut counter = RequireCounter { requires, counts: HashMap::new(), order: Vec::new() };
+    counter.visit_ast(&ast);
+
+    let mut taken_names = IdentifierCollector::default();
+    taken_names.visit_ast(&ast);
+    let mut taken_names = taken_names.identifiers;
+
+    // Locals of the modules bound so far, and the statement each binding goes before
+    let mut bindings: HashMap<String, String> = HashMap::new();
+    let mut bindings_before: HashMap<usize, Vec<(String, String)>> = HashMap::new();
+    let mut seen = HashSet::new();
+    for (index, (stmt, _)) in ast.nodes().stmts_with_semicolon().enumerate() {
+        if !is_import_statement(stmt) {
+            break;
+        }
+        let mut stmt_counter = RequireCounter { requires, counts: HashMap::new(), order: Vec::new() };
+        stmt.visit(&mut stmt_counter);
+
+        // A binding declared before the statement runs its require first, which only keeps the
+        // order if every require before it in the statement has already run. A module an earlier
+        // statement required without a binding would be used before it was declared.
+        let mut can_bind = true;
+        for (target, require) in stmt_counter.order {
+            if bindings.contains_key(&target) {
+                continue;
+            }
+            can_bind &= counter.counts[&target] >= 2 && !seen.contains(&target);
+            if can_bind {
+                let name = unique_name(&requires[&require].name, &mut taken_names);
+                bindings.insert(target.clone(), name.clone());
+                bindings_before.entry(index).or_default().push((name, require));
+            }
+            seen.insert(target);
+        }
+    }
+    if bindings.is_empty() {
+        return ast;
+    }
+
+    let ast = RequireReplacer { requires, bindings: &bindings }.visit_ast(ast);
+    let mut stmts = Vec::new();
+    for (index, stmt) in ast.nodes().stmts_with_semicolon().enumerate() {
+        for (name, require) in bindings_before.remove(&index).unwrap_or_default() {
+            stmts.extend(binding_statement(&name, &require));
+        }
+        stmts.push(stmt.clone());
+    }
+    let nodes = ast.nodes().clone().with_stmts(stmts);
+    ast.with_nodes(nodes)
+}
+
+/// `local <name> = <require>` on a line of its own.
+fn binding_statement(name: &str, require: &str) -> Option<(Stmt, Option<TokenReference>)> {
+    let parsed = parse_fallible(&format!("local {} = {}\n", name, require), LuaVersion::luau());
+    parsed.ast().nodes().stmts_with_semicolon().next().cloned()
+}
+
+/// An emitted require at the start of an expression, such as `require(...)` in
+/// `require(...).Member`.
+struct LeadingRequire<'a> {
+    /// The require as code, without trivia.
+    code: String,
+    name: &'a TokenReference,
+    right_paren: &'a TokenReference,
+    /// What the expression goes on with after the require.
+    rest: Vec<Suffix>,
+}
+
+/// The emitted require `expression` starts with, if any. The require is its own expression
+/// when the transformer built it, but a call on a name like any other when the code is parsed.
+fn leading_require<'a>(
+    expression: &'a Expression,
+    requires: &HashMap<String, EmittedRequire>
+) -> Option<LeadingRequire<'a>> {
+    let (prefix, mut suffixes): (_, Box<dyn Iterator<Item = &Suffix>>) = match expression {
+        Expression::FunctionCall(function_call) => {
+            (function_call.prefix(), Box::new(function_call.suffixes()))
+        }
+        Expression::Var(Var::Expression(var_expression)) => {
+            (var_expression.prefix(), Box::new(var_expression.suffixes()))
+        }
+        _ => {
+            return None;
+        }
+    };
+    let Prefix::Name(name) = prefix else {
+        return None;
+    };
+    let Some(Suffix::Call(Call::AnonymousCall(FunctionArgs::Parentheses { parentheses, arguments }))) =
+        suffixes.next() else {
+        return None;
+    };
+    if name.token().to_string() != "require" {
+        return None;
+    }
+
+    // The rewrite moves the trivia around the import onto the call, but the code was recorded
+    // without it
+    let (left_paren, right_paren) = parentheses.tokens();
+    let code = format!("{}{}{}{}", name.token(), left_paren, arguments, right_paren.token());
+    requires.contains_key(&code).then(|| LeadingRequire {
+        code,
+        name,
+        right_paren,
+        rest: suffixes.cloned().collect(),
+    })
+}
+
+/// Whether `stmt` is one of roblox-ts's imports: a `local` whose values only require modules
+/// and index into them.
+fn is_import_statement(stmt: &Stmt) -> bool {
+    let Stmt::LocalAssignment(local_assignment) = stmt else {
+        return false;
+    };
+    local_assignment.expressions().iter().all(|expression| {
+        match expression {
+            Expression::FunctionCall(function_call) => is_require_call(function_call),
+            Expression::Var(Var::Expression(var_expression)) => {
+                let is_require_prefix = match var_expression.prefix() {
+                    Prefix::Expression(prefix) =>
+                        matches!(&**prefix, Expression::FunctionCall(function_call) if is_require_call(function_call)),
+                    Prefix::Name(name) => name.token().to_string() == "require",
+                    _ => false,
+                };
+                is_require_prefix &&
+                    var_expression.suffixes().all(|suffix| {
+                        matches!(suffix, Suffix::Index(Index::Dot { .. } | Index::Brackets { .. }) | Suffix::Call(_))
+                    })
+            }
+            _ => false,
+        }
+    })
+}
+
+fn is_require_call(function_call: &FunctionCall) -> bool {
+    matches!(function_call.prefix(), Prefix::Name(name) if name.token().to_string() == "require")
+}
+
+/// `_<hint>` with anything that cannot appear in an identifier replaced, numbered when the file
+/// already uses the name.
+fn unique_name(hint: &str, taken_names: &mut HashSet<String>) -> String {
+    let base: String = std::iter
+        ::once('_')
+        .chain(hint.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }))
+        .collect();
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while taken_names.contains(&name) {
+        name = format!("{}_{}", base, suffix);
+        suffix += 1;
+    }
+    taken_names.insert(name.clone());
+    name
+}
+
+/// Counts how often each module is required, remembering the order they are first required in
+/// and how.
+struct RequireCounter<'a> {
+    requires: &'a HashMap<String, EmittedRequire>,
+    counts: HashMap<String, usize>,
+    order: Vec<(String, String)>,
+}
+
+impl Visitor for RequireCounter<'_> {
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Some(require) = leading_require(expression, self.requires) {
+            let target = self.requires[&require.code].target.clone();
+            let count = self.counts.entry(target.clone()).or_default();
+            if *count == 0 {
+                self.order.push((target, require.code));
+            }
+            *count += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct IdentifierCollector {
+    identifiers: HashSet<String>,
+}
+
+impl Visitor for IdentifierCollector {
+    fn visit_identifier(&mut self, token: &Token) {
+        self.identifiers.insert(token.to_string());
+    }
+}
+
+/// Replaces each bound require with its local, keeping the trivia around it.
+struct RequireReplacer<'a> {
+    requires: &'a HashMap<String, EmittedRequire>,
+    bindings: &'a HashMap<String, String>,
+}
+
+impl VisitorMut for RequireReplacer<'_> {
+    fn visit_expression(&mut self, expression: Expression) -> Expression {
+        let Some(require) = leading_require(&expression, self.requires) else {
+            return expression;
+        };
+        let Some(name) = self.bindings.get(&self.requires[&require.code].target) else {
+            return expression;
+        };
+
+        let name = TokenReference::new(
+            require.name.leading_trivia().cloned().collect(),
+            Token::new(TokenType::Identifier { identifier: name.as_str().into() }),
+            require.right_paren.trailing_trivia().cloned().collect()
+        );
+        if require.rest.is_empty() {
+            return Expression::Var(Var::Name(name));
+        }
+        match expression {
+            Expression::FunctionCall(_) => {
+                Expression::FunctionCall(
+                    FunctionCall::new(Prefix::Name(name)).with_suffixes(require.rest)
+                )
+            }
+            _ => {
+                Expression::Var(
+                    Var::Expression(
+                        Box::new(VarExpression::new(Prefix::Name(name)).with_suffixes(require.rest))
+                    )
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const B: &str = "require(script.Parent:FindFirstChild(\"b\"))";
+
+    fn hoist(code: &str, requires: &[(&str, &str, &str)]) -> String {
+        let requires = requires
+            .iter()
+            .map(|(code, target, name)| {
+                let emitted = EmittedRequire { target: target.to_string(), name: name.to_string() };
+                (code.to_string(), emitted)
+            })
+            .collect();
+        let ast = full_moon::parse_fallible(code, LuaVersion::luau()).into_ast();
+        hoist_requires(ast, &requires).to_string()
+    }
+
+    #[test]
+    fn binds_repeated_requires_before_their_first_use() {
+        let code = format!(
+            "local TS = require(script.TS)\nlocal b = {B}.x\nlocal c = require(script.c)\nlocal b2 = {B}.y\n"
+        );
+        assert_eq!(
+            hoist(&code, &[(B, "game.b", "b"), ("require(script.c)", "game.c", "c")]),
+            format!(
+                "local TS = require(script.TS)\nlocal _b = {B}\nlocal b = _b.x\nlocal c = require(script.c)\nlocal b2 = _b.y\n"
+            )
+        );
+    }
+
+    #[test]
+    fn binds_differently_written_requires_of_the_same_module() {
+        let other = "require(script.Parent.Parent.x.b)";
+        let code = format!("local b = {B}\nlocal b2 = {other}\n");
+        assert_eq!(
+            hoist(&code, &[(B, "game.b", "b"), (other, "game.b", "b")]),
+            format!("local _b = {B}\nlocal b = _b\nlocal b2 = _b\n")
+        );
+    }
+
+    #[test]
+    fn keeps_requires_that_would_run_earlier() {
+        // Binding `b` before the statement would run it before `c`
+        let code = format!("local c, b = require(script.c), {B}\nlocal b2 = {B}\n");
+        let requires = [(B, "game.b", "b"), ("require(script.c)", "game.c", "c")];
+        assert_eq!(hoist(&code, &requires), code);
+    }
+
+    #[test]
+    fn only_binds_in_the_imports_at_the_top() {
+        let code = format!("local x = 1\nlocal b = {B}\nlocal b2 = {B}\n");
+        assert_eq!(hoist(&code, &[(B, "game.b", "b")]), code);
+    }
+
+    #[test]
+    fn avoids_names_the_file_uses() {
+        let code = format!("local b = {B}\nlocal b2 = {B}\nlocal _b = 1\n");
+        assert_eq!(
+            hoist(&code, &[(B, "game.b", "b")]),
+            format!("local _b_2 = {B}\nlocal b = _b_2\nlocal b2 = _b_2\nlocal _b = 1\n")
+        );
+    }
+
+    #[test]
+    fn binds_requires_with_comments_around_them() {
+        let code = format!(
+            "local b1 = {B} -- first\nlocal b2 = {B} -- second\nlocal b3 = --[[inline]] {B}\n"
+        );
+        assert_eq!(
+            hoist(&code, &[(B, "game.b", "b")]),
+            format!(
+                "local _b = {B}\nlocal b1 = _b -- first\nlocal b2 = _b -- second\nlocal b3 = --[[inline]] _b\n"
+            )
+        );
+    }
+}
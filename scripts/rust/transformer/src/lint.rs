@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use walkdir::WalkDir;
+
+use crate::{
+    config::Settings,
+    diagnostics::{ Code, Diagnostic, Emitter, Severity },
+    mount_root,
+    read_sourcemap,
+    Inputs,
+    RobloxPath,
+    SourcemapData,
+    SourcemapNode,
+};
+
+/// Roblox does not allow instance names longer than this many characters.
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Reports what makes the emitted `FindFirstChild` chains unreliable: siblings sharing a name,
+/// names that cannot be reproduced, and scripts Rojo will not sync. Findings are warnings, or
+/// errors with `--check`.
+pub fn lint_sourcemap(
+    settings: &Settings,
+    inputs: &Inputs,
+    maps: &SourcemapData,
+    emitter: &mut Emitter
+) {
+    let severity = if settings.check { Severity::Error } else { Severity::Warning };
+
+    let mut data_model: Option<RobloxPath> = None;
+    for sourcemap in inputs.sourcemaps() {
+        // Problems reading the sourcemap were already reported when it was loaded
+        let Ok(mut root) = read_sourcemap(&sourcemap.path, &mut Vec::new()) else {
+            continue;
+        };
+        let data_model = data_model.get_or_insert_with(|| RobloxPath::root(&root.name));
+        let parent = mount_root(&mut root, &sourcemap.mount, data_model);
+
+        let mut diagnostics = Vec::new();
+        lint_node(&root, parent.as_ref(), severity, &mut diagnostics);
+        emitter.emit_all(
+            diagnostics.into_iter().map(|diagnostic| diagnostic.with_file(&sourcemap.path))
+        );
+    }
+
+    lint_unmapped_files(settings, inputs, maps, severity, emitter);
+}
+
+fn lint_node(
+    node: &SourcemapNode,
+    parent: Option<&RobloxPath>,
+    severity: Severity,
+    diagnostics: &mut Vec<Diagnostic>
+) {
+    let roblox_path = match parent {
+        Some(parent) => parent.join(&node.name),
+        None => RobloxPath::root(&node.name),
+    };
+
+    if let Some(reason) = unsafe_name_reason(&node.name) {
+        diagnostics.push(
+            Diagnostic::new(
+                severity,
+                Code::UnsafeName,
+                format!("the name of `{}` cannot be required reliably: {}", roblox_path, reason)
+            )
+        );
+    }
+
+    let mut siblings: BTreeMap<&str, Vec<&SourcemapNode>> = BTreeMap::new();
+    for child in &node.children {
+        siblings.entry(child.name.as_str()).or_default().push(child);
+    }
+    for (name, siblings) in siblings.iter().filter(|(_, siblings)| siblings.len() > 1) {
+        let mut diagnostic = Diagnostic::new(
+            severity,
+            Code::DuplicateName,
+            format!("{} instances are called `{}`", siblings.len(), roblox_path.join(name))
+        );
+        for sibling in siblings {
+            let class_name = if sibling.class_name.is_empty() {
+                "instance"
+            } else {
+                sibling.class_name.as_str()
+            };
+            diagnostic = diagnostic.with_note(match sibling.file_paths.first() {
+                Some(file_path) => format!("a {} from {}", class_name, file_path),
+                None => format!("a {} without a file", class_name),
+            });
+        }
+        diagnostics.push(
+            diagnostic.with_note("`FindFirstChild` returns whichever of them Roblox finds first")
+        );
+    }
+
+    for child in &node.children {
+        lint_node(child, Some(&roblox_path), severity, diagnostics);
+    }
+}
+
+/// Why `name` cannot be reproduced in an emitted `FindFirstChild` call, if it cannot.
+fn unsafe_name_reason(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("it is empty")
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        Some("Roblox does not allow names longer than 100 characters")
+    } else if name.chars().any(char::is_control) {
+        Some("it contains control characters")
+    } else {
+        None
+    }
+}
+
+/// Reports scripts under the transform directory that no sourcemap lists, since nothing can
+/// require them and their own imports cannot be resolved.
+fn lint_unmapped_files(
+    settings: &Settings,
+    inputs: &Inputs,
+    maps: &SourcemapData,
+    severity: Severity,
+    emitter: &mut Emitter
+) {
+    let mut walker = WalkDir::new(&inputs.transform_path).sort_by_file_name().into_iter();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        if inputs.out_path.as_deref() == Some(entry.path()) {
+            walker.skip_current_dir();
+            continue;
+        }
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let relative_path = entry
+            .path()
+            .strip_prefix(&inputs.transform_path)
+            .unwrap_or(entry.path());
+        if
+            !settings.file_filter.matches(relative_path) ||
+            maps.fs_to_roblox.contains_key(entry.path())
+        {
+            continue;
+        }
+        emitter.emit(
+            Diagnostic::new(severity, Code::UnmappedFile, "file is not in the sourcemap")
+                .with_file(entry.path())
+                .with_note("add it to the Rojo project, or skip it with --exclude")
+        );
+    }
+}
@@ -16,18 +16,65 @@ use full_moon::{
         punctuated::{ Pair, Punctuated },
     },
     parse_fallible,
+    node::Node,
     tokenizer::{ Token, TokenReference, TokenType, Symbol, StringLiteralQuoteType },
     visitors::VisitorMut,
     LuaVersion,
 };
-use serde::Deserialize;
-use std::{ collections::HashMap, env, fs, path::{ Path, PathBuf }, thread };
+use serde::{ Deserialize, Serialize };
+use similar::TextDiff;
+use std::{
+    cell::RefCell,
+    collections::{ BTreeMap, HashMap, HashSet },
+    env,
+    fs,
+    path::{ Path, PathBuf },
+    sync::{ atomic::{ AtomicUsize, Ordering }, mpsc },
+    thread,
+};
 use walkdir::WalkDir;
 
+use cache::{ Cache, CacheEntry };
+use config::{
+    DiagnosticsLevel,
+    EmitStyle,
+    ExtraSourcemap,
+    GetServiceConfig,
+    Settings,
+    WaitForChildConfig,
+};
+use diagnostics::{ Code, Diagnostic, Emitter, Severity, Span };
+use hoist::EmittedRequire;
+use roblox_path::{
+    escape_string,
+    is_identifier,
+    is_instance_member,
+    is_service,
+    unescape_string,
+    RequirePath,
+    RobloxPath,
+    Service,
+};
+use string_require::{ load_aliases, string_require_path, Alias };
+
+mod cache;
+mod cli;
+mod config;
+mod diagnostics;
+mod hoist;
+mod lint;
+mod project;
+mod roblox_path;
+mod string_require;
+mod watch;
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 struct SourcemapNode {
     name: String,
+    /// Empty when the class is unknown, e.g. for instances loaded from model files.
+    #[serde(default)]
+    class_name: String,
     #[serde(default)]
     children: Vec<SourcemapNode>,
     #[serde(default)]
@@ -35,90 +82,759 @@ struct SourcemapNode {
 }
 
 struct SourcemapData {
-    roblox_to_fs: HashMap<String, PathBuf>,
-    fs_to_roblox: HashMap<PathBuf, String>,
-    fs_projects: Vec<PathBuf>,
+    /// Sorted, so the files of an instance and its descendants are next to each other.
+    roblox_to_fs: BTreeMap<RobloxPath, PathBuf>,
+    fs_to_roblox: HashMap<PathBuf, RobloxPath>,
+    /// Class of every instance in the sourcemap, including those without files such as Folders.
+    class_names: HashMap<RobloxPath, String>,
+    /// Every instance keyed by its own name, each list sorted, so packages are found by their
+    /// last segment in a stable order instead of by scanning every path.
+    paths_by_name: HashMap<String, Vec<RobloxPath>>,
+    /// Instances backed by a nested `*.project.json`, which is how every package under
+    /// `node_modules` is synced. The project of the sourcemap itself is not included.
+    package_roots: HashSet<RobloxPath>,
+    /// Instances whose source file is listed in the sourcemap but does not exist, with the path
+    /// it was expected at.
+    missing_files: HashMap<RobloxPath, PathBuf>,
+    /// Name of every service directly under the DataModel, keyed by its class, so
+    /// `game:GetService(...)` can be followed into the sourcemap.
+    service_names: HashMap<String, String>,
+}
+
+impl SourcemapData {
+    fn roblox_path_of(&self, fs_path: &Path) -> Option<&RobloxPath> {
+        self.fs_to_roblox.get(fs_path)
+    }
+
+    fn class_name_of(&self, roblox_path: &RobloxPath) -> Option<&String> {
+        self.class_names.get(roblox_path).filter(|class_name| !class_name.is_empty())
+    }
+
+    fn contains(&self, roblox_path: &RobloxPath) -> bool {
+        self.class_names.contains_key(roblox_path)
+    }
+
+    fn fs_path_of(&self, roblox_path: &RobloxPath) -> Option<&PathBuf> {
+        self.roblox_to_fs.get(roblox_path)
+    }
+
+    /// Every instance whose path ends with `package_path`, in sorted order. This includes
+    /// instances without files, since a package can resolve to a Folder whose `init` child is the
+    /// actual module. When the sourcemap knows where packages start, only candidates inside a
+    /// package named by `package_path` are kept, so a folder that merely happens to be called
+    /// `node_modules` is not mistaken for one.
+    fn package_candidates(&self, package_path: &[String]) -> Vec<&RobloxPath> {
+        let Some(name) = package_path.last() else {
+            return Vec::new();
+        };
+        let candidates: Vec<&RobloxPath> = self.paths_by_name
+            .get(name)
+            .map(|paths| paths.iter().filter(|path| path.ends_with(package_path)).collect())
+            .unwrap_or_default();
+
+        // node_modules, then the scope if there is one, then the package name
+        let scoped = package_path.get(1).is_some_and(|scope| scope.starts_with('@'));
+        let package_len = if scoped { 3 } else { 2 };
+        let scoped_candidates: Vec<&RobloxPath> = candidates
+            .iter()
+            .copied()
+            .filter(|candidate| {
+                let base_len = candidate.len() - package_path.len();
+                self.package_roots.contains(&candidate.truncated(base_len + package_len))
+            })
+            .collect();
+        if scoped_candidates.is_empty() { candidates } else { scoped_candidates }
+    }
+
+    fn missing_file_of(&self, roblox_path: &RobloxPath) -> Option<&PathBuf> {
+        self.missing_files.get(roblox_path)
+    }
+
+    fn service_name_of(&self, class_name: &str) -> Option<&String> {
+        self.service_names.get(class_name)
+    }
+
+    /// The innermost package `roblox_path` belongs to, if any.
+    fn package_of(&self, roblox_path: &RobloxPath) -> Option<RobloxPath> {
+        (1..=roblox_path.len())
+            .rev()
+            .map(|len| roblox_path.truncated(len))
+            .find(|ancestor| self.package_roots.contains(ancestor))
+    }
+
+    /// Files of `roblox_path` and all of its descendants, in sorted order.
+    fn files_under<'s>(&'s self, roblox_path: &'s RobloxPath) -> impl Iterator<Item = &'s PathBuf> {
+        self.roblox_to_fs
+            .range(roblox_path.clone()..)
+            .take_while(|(path, _)| path.starts_with(roblox_path.segments()))
+            .map(|(_, fs_path)| fs_path)
+    }
+
+    fn is_pinned(&self, candidate: &RobloxPath, pin: &Path) -> bool {
+        self.files_under(candidate).any(|fs_path| fs_path.starts_with(pin))
+    }
+
+    /// The candidates `importer` can reach by looking in the `node_modules` of each of its
+    /// ancestors, like `TS.getModule` does at runtime, in sorted order.
+    fn reachable_candidates(&self, importer: &RobloxPath, package_path: &[String]) -> Vec<&RobloxPath> {
+        self.package_candidates(package_path)
+            .into_iter()
+            .filter(|candidate| {
+                importer.starts_with(&candidate.segments()[..candidate.len() - package_path.len()])
+            })
+            .collect()
+    }
+
+    /// Picks the reachable candidate in the `node_modules` nearest to `importer`. None when no
+    /// candidate is reachable, since any other copy is not the one the import means. A `pin`
+    /// restricts the choice to candidates with files under it, unless it matches none of them.
+    fn find_package(
+        &self,
+        importer: &RobloxPath,
+        package_path: &[String],
+        pin: Option<&Path>
+    ) -> Option<&RobloxPath> {
+        let mut candidates = self.reachable_candidates(importer, package_path);
+        if let Some(pin) = pin {
+            let pinned: Vec<&RobloxPath> = candidates
+                .iter()
+                .copied()
+                .filter(|candidate| self.is_pinned(candidate, pin))
+                .collect();
+            if !pinned.is_empty() {
+                candidates = pinned;
+            }
+        }
+        // Every reachable candidate sits right below an ancestor of the importer, so the longest
+        // is the nearest
+        candidates.into_iter().max_by_key(|candidate| candidate.len())
+    }
+}
+
+/// A query a file's transformation made against the sourcemap, or against the `.luaurc` files
+/// string requires use. Recorded so an incremental run can tell whether a change affects the
+/// file without parsing it again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", content = "key", rename_all = "camelCase")]
+enum Lookup {
+    Script(PathBuf),
+    Package {
+        importer: RobloxPath,
+        path: Vec<String>,
+        pin: Option<PathBuf>,
+    },
+    ClassName(RobloxPath),
+    ServiceName(String),
+    Instance(RobloxPath),
+    OwningPackage(RobloxPath),
+    MissingFile(RobloxPath),
+    File(RobloxPath),
+    LuauAliases(PathBuf),
+}
+
+impl Lookup {
+    fn resolve(&self, sourcemap_data: &SourcemapData) -> Option<String> {
+        match self {
+            Lookup::Script(fs_path) => sourcemap_data.roblox_path_of(fs_path).map(RobloxPath::to_string),
+            // The other candidates are part of the result, since they decide whether the import
+            // is reported as ambiguous
+            Lookup::Package { importer, path, pin } => {
+                let candidates: Vec<String> = sourcemap_data
+                    .package_candidates(path)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                sourcemap_data
+                    .find_package(importer, path, pin.as_deref())
+                    .map(|chosen| format!("{} of {}", chosen, candidates.join(", ")))
+            }
+            Lookup::ClassName(roblox_path) => sourcemap_data.class_name_of(roblox_path).cloned(),
+            Lookup::ServiceName(class_name) => sourcemap_data.service_name_of(class_name).cloned(),
+            Lookup::Instance(roblox_path) => sourcemap_data.contains(roblox_path).then(String::new),
+            Lookup::File(roblox_path) => {
+                sourcemap_data.fs_path_of(roblox_path).map(|fs_path| fs_path.display().to_string())
+            }
+            Lookup::LuauAliases(dir) => Some(format!("{:?}", load_aliases(dir, &mut Vec::new()))),
+            Lookup::MissingFile(roblox_path) => {
+                sourcemap_data.missing_file_of(roblox_path).map(|fs_path| fs_path.display().to_string())
+            }
+            Lookup::OwningPackage(roblox_path) => {
+                sourcemap_data.package_of(roblox_path).as_ref().map(RobloxPath::to_string)
+            }
+        }
+    }
+}
+
+/// What an entry of `filePaths` contributes to its instance. Ordered by how well the file
+/// identifies the instance, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FileKind {
+    /// A `.lua` or `.luau` script, which is what the transformer rewrites.
+    Script,
+    /// A file turned into a ModuleScript or value, such as `.json`, `.toml`, `.txt` or `.csv`.
+    Data,
+    /// A `.model.json`, `.rbxm` or `.rbxmx` file.
+    Model,
+    /// A `.meta.json` file adjusting the instance created from the other files.
+    Meta,
+    /// A `.project.json` file the instance was synced from.
+    Project,
+}
+
+impl FileKind {
+    fn of(file_path: &str) -> Option<FileKind> {
+        let file_path = file_path.to_ascii_lowercase();
+        if file_path.ends_with(".meta.json") {
+            Some(FileKind::Meta)
+        } else if file_path.ends_with(".project.json") {
+            Some(FileKind::Project)
+        } else if [".model.json", ".rbxm", ".rbxmx"].iter().any(|ext| file_path.ends_with(ext)) {
+            Some(FileKind::Model)
+        } else if [".lua", ".luau"].iter().any(|ext| file_path.ends_with(ext)) {
+            Some(FileKind::Script)
+        } else if [".json", ".toml", ".txt", ".csv"].iter().any(|ext| file_path.ends_with(ext)) {
+            Some(FileKind::Data)
+        } else {
+            None
+        }
+    }
+}
+
+/// The part of a `.meta.json` file that matters for requires. Properties, attributes and the
+/// rest only affect the instance once it is built.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MetaFile {
+    class_name: Option<String>,
+}
+
+fn read_meta_file(path: &Path, diagnostics: &mut Vec<Diagnostic>) -> Option<MetaFile> {
+    let meta = fs
+        ::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|content| serde_json::from_str(&content).map_err(|err| err.to_string()));
+    match meta {
+        Ok(meta) => Some(meta),
+        Err(err) => {
+            diagnostics.push(
+                Diagnostic::warning(Code::SourcemapParse, format!("cannot read meta file: {}", err))
+                    .with_file(path)
+            );
+            None
+        }
+    }
 }
 
 fn build_path_maps(
     node: &SourcemapNode,
     maps: &mut SourcemapData,
-    current_roblox_path: &str,
-    base_dir: &Path
+    parent_roblox_path: Option<&RobloxPath>,
+    base_dir: &Path,
+    diagnostics: &mut Vec<Diagnostic>
 ) {
-    let new_roblox_path = if current_roblox_path.is_empty() {
-        node.name.split('.').last().unwrap_or("").to_string()
-    } else {
-        format!("{}.{}", current_roblox_path, node.name)
+    let new_roblox_path = match parent_roblox_path {
+        Some(parent) => parent.join(&node.name),
+        None => RobloxPath::root(&node.name),
     };
+    let same_name = maps.paths_by_name.entry(node.name.clone()).or_default();
+    if let Err(index) = same_name.binary_search(&new_roblox_path) {
+        same_name.insert(index, new_roblox_path.clone());
+    }
 
-    if let Some(file_path) = node.file_paths.get(0) {
-        let fs_path = dunce
-            ::canonicalize(Path::new(&base_dir.join(file_path)))
-            .expect(&format!("Failed to canonicalize {}", file_path));
+    // Rojo lists files in no particular order, so the source is picked by kind rather than position
+    let mut source: Option<(FileKind, &String)> = None;
+    let mut class_name = node.class_name.clone();
+    for file_path in &node.file_paths {
+        match FileKind::of(file_path) {
+            Some(FileKind::Meta) => {
+                let Some(meta) = read_meta_file(&base_dir.join(file_path), diagnostics) else {
+                    continue;
+                };
+                // Meta files can only turn a Folder into another class
+                if let Some(meta_class_name) = meta.class_name
+                    && (class_name.is_empty() || class_name == "Folder")
+                {
+                    class_name = meta_class_name;
+                }
+            }
+            Some(FileKind::Project) if parent_roblox_path.is_some() => {
+                maps.package_roots.insert(new_roblox_path.clone());
+            }
+            Some(FileKind::Project) => {}
+            Some(kind) if source.is_none_or(|(source_kind, _)| kind < source_kind) => {
+                source = Some((kind, file_path));
+            }
+            Some(_) => {}
+            None => {}
+        }
+    }
+    let in_data_model = parent_roblox_path.is_some_and(|parent| {
+        parent.len() == 1 && maps.class_names.get(parent).is_some_and(|class_name| class_name == "DataModel")
+    });
+    if in_data_model && is_service(&class_name) {
+        maps.service_names.entry(class_name.clone()).or_insert_with(|| node.name.clone());
+    }
+    maps.class_names.insert(new_roblox_path.clone(), class_name);
+
+    if let Some((_, file_path)) = source {
+        let fs_path = match dunce::canonicalize(Path::new(&base_dir.join(file_path))) {
+            Ok(fs_path) => fs_path,
+            Err(err) => {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        Code::SourcemapPathMissing,
+                        format!("sourcemap entry `{}` points at {}: {}", new_roblox_path, file_path, err)
+                    ).with_note("imports of this instance are left untouched")
+                );
+                maps.missing_files.insert(new_roblox_path.clone(), base_dir.join(file_path));
+                for child in &node.children {
+                    build_path_maps(child, maps, Some(&new_roblox_path), base_dir, diagnostics);
+                }
+                return;
+            }
+        };
 
         maps.roblox_to_fs.insert(new_roblox_path.clone(), fs_path.clone());
         maps.fs_to_roblox.insert(fs_path.clone(), new_roblox_path.clone());
-
-        for project_file_path in node.file_paths.iter().skip(1) {
-            if project_file_path.ends_with(".project.json") {
-                maps.fs_projects.push(fs_path.clone());
-            }
-        }
     }
 
     for child in &node.children {
-        build_path_maps(child, maps, &new_roblox_path, base_dir);
+        build_path_maps(child, maps, Some(&new_roblox_path), base_dir, diagnostics);
     }
 }
 
-fn roblox_path_to_luau_require(source_roblox_path: &str, target_roblox_path: &str) -> String {
-    let source_parts: Vec<&str> = source_roblox_path.split('.').collect();
-    let target_parts: Vec<&str> = target_roblox_path.split('.').collect();
+/// The name a package is pinned by: `@scope/name` for scoped packages, otherwise just `name`.
+fn package_name(module_path: &[String]) -> String {
+    match module_path {
+        [scope, name, ..] if scope.starts_with('@') => format!("{}/{}", scope, name),
+        [name, ..] => name.clone(),
+        [] => String::new(),
+    }
+}
 
-    let mut common_len = 0;
-    while
-        common_len < source_parts.len() &&
-        common_len < target_parts.len() &&
-        source_parts[common_len] == target_parts[common_len]
-    {
-        common_len += 1;
+/// How a package is named in diagnostics and the report: the path below the innermost
+/// `node_modules`, such as `@rbxts/t`, or the full Roblox path for packages outside of one.
+fn package_display_name(package_root: &RobloxPath) -> String {
+    let segments = package_root.segments();
+    match segments.iter().rposition(|segment| segment == "node_modules") {
+        Some(index) if index + 1 < segments.len() => segments[index + 1..].join("/"),
+        _ => package_root.to_string(),
     }
+}
+
+/// A double-quoted string literal holding `value`.
+fn string_expression(value: &str) -> Expression {
+    Expression::String(
+        TokenReference::new(
+            Vec::new(),
+            Token::new(TokenType::StringLiteral {
+                literal: escape_string(value).into(),
+                multi_line_depth: 0,
+                quote_type: StringLiteralQuoteType::Double,
+            }),
+            Vec::new()
+        )
+    )
+}
+
+/// `:<method>(<arguments>)`
+fn create_method_call(method: &str, arguments: Punctuated<Expression>) -> Suffix {
+    Suffix::Call(
+        Call::MethodCall(
+            MethodCall::new(
+                TokenReference::new(
+                    Vec::new(),
+                    Token::new(TokenType::Identifier { identifier: method.into() }),
+                    Vec::new()
+                ),
+                FunctionArgs::Parentheses {
+                    parentheses: ContainedSpan::new(
+                        TokenReference::new(
+                            Vec::new(),
+                            Token::new(TokenType::Symbol { symbol: Symbol::LeftParen }),
+                            Vec::new()
+                        ),
+                        TokenReference::new(
+                            Vec::new(),
+                            Token::new(TokenType::Symbol { symbol: Symbol::RightParen }),
+                            Vec::new()
+                        )
+                    ),
+                    arguments,
+                }
+            )
+        )
+    )
+}
+
+/// The name a require path starts from, followed by its `:GetService("...")` call when it is
+/// rooted at a service.
+fn create_require_root(require_path: &RequirePath) -> (Prefix, Vec<Suffix>) {
+    let prefix = Prefix::Name(
+        TokenReference::new(
+            Vec::new(),
+            Token::new(TokenType::Identifier { identifier: require_path.root.as_str().into() }),
+            Vec::new()
+        )
+    );
+    let suffixes = require_path.service
+        .iter()
+        .map(|service| {
+            let arguments = Punctuated::from_iter([Pair::End(string_expression(&service.class_name))]);
+            create_method_call("GetService", arguments)
+        })
+        .collect();
+    (prefix, suffixes)
+}
+
+/// `, ` between two call arguments.
+fn comma_token() -> TokenReference {
+    TokenReference::new(
+        Vec::new(),
+        Token::new(TokenType::Symbol { symbol: Symbol::Comma }),
+        vec![Token::new(TokenType::Whitespace { characters: " ".into() })]
+    )
+}
+
+/// `expression` carrying the trivia that surrounded the code it replaces, so comments and line
+/// breaks around a rewritten import stay where they were. Only the generated `require(...)` calls
+/// have a place to put it; anything else is returned unchanged.
+fn with_surrounding_trivia(
+    expression: Expression,
+    leading_trivia: Vec<Token>,
+    trailing_trivia: Vec<Token>
+) -> Expression {
+    let Expression::FunctionCall(function_call) = expression else {
+        return expression;
+    };
+    let prefix = match function_call.prefix() {
+        Prefix::Name(name) => {
+            Prefix::Name(
+                TokenReference::new(
+                    leading_trivia,
+                    name.token().clone(),
+                    name.trailing_trivia().cloned().collect()
+                )
+            )
+        }
+        prefix => prefix.clone(),
+    };
+    let mut suffixes: Vec<Suffix> = function_call.suffixes().cloned().collect();
+    let Some(
+        Suffix::Call(Call::AnonymousCall(FunctionArgs::Parentheses { parentheses, arguments })),
+    ) = suffixes.pop() else {
+        return Expression::FunctionCall(function_call);
+    };
+    let (left_paren, right_paren) = parentheses.tokens();
+    let right_paren = TokenReference::new(
+        right_paren.leading_trivia().cloned().collect(),
+        right_paren.token().clone(),
+        trailing_trivia
+    );
+    suffixes.push(
+        Suffix::Call(
+            Call::AnonymousCall(FunctionArgs::Parentheses {
+                parentheses: ContainedSpan::new(left_paren.clone(), right_paren),
+                arguments,
+            })
+        )
+    );
+    Expression::FunctionCall(function_call.with_prefix(prefix).with_suffixes(suffixes))
+}
 
-    let mut result_parts = vec!["script".to_string()];
-    let parents_needed = source_parts.len() - common_len;
-    result_parts.extend(std::iter::repeat("Parent".to_string()).take(parents_needed));
-    result_parts.extend(target_parts[common_len..].iter().map(|s| s.to_string()));
+/// The trivia before and after `node`, for handing over to the code replacing it.
+fn surrounding_trivia(node: &impl Node) -> (Vec<Token>, Vec<Token>) {
+    // Bracket pairs come before what they contain in `tokens()`, so order them by position
+    let first = node.tokens().min_by_key(|token| token.start_position());
+    let last = node.tokens().max_by_key(|token| token.end_position());
+    (
+        first.map(|token| token.leading_trivia().cloned().collect()).unwrap_or_default(),
+        last.map(|token| token.trailing_trivia().cloned().collect()).unwrap_or_default(),
+    )
+}
 
-    result_parts.join(".")
+/// A require target that exists in the sourcemap but cannot be required.
+enum UnusableTarget {
+    NotAModule {
+        roblox_path: RobloxPath,
+        class_name: String,
+    },
+    /// The sourcemap lists a file for the target that no longer exists, so Rojo will not sync it.
+    MissingFile {
+        roblox_path: RobloxPath,
+        fs_path: PathBuf,
+    },
+    /// The sourcemap has no such instance, or the path climbs above its root when `None`.
+    NotInSourcemap {
+        roblox_path: Option<RobloxPath>,
+    },
 }
 
 // --- TSTransformer ---
 
+/// A node that was replaced with a `require(...)` call, as written to the `--report` file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Rewrite {
+    #[serde(flatten)]
+    span: Span,
+    original: String,
+    resolved_path: String,
+    emitted: String,
+    /// The package the required module belongs to, such as `@rbxts/t`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+}
+
 struct TSTransformer<'a> {
     current_fs_path: &'a Path,
     sourcemap_data: &'a SourcemapData,
     runtime_fs_path: &'a Path,
+    emit_style: EmitStyle,
+    wait_for_child: &'a WaitForChildConfig,
+    get_service: &'a GetServiceConfig,
+    package_pins: &'a BTreeMap<String, PathBuf>,
+    /// Severity given to imports that cannot be resolved; `--check` turns them into errors.
+    unresolved_severity: Severity,
+    /// Severity given to imports of instances whose file is missing; `--strict-sourcemap` turns
+    /// them into errors even without `--check`.
+    missing_file_severity: Severity,
+    diagnostics: Vec<Diagnostic>,
+    rewrites: Vec<Rewrite>,
+    lookups: RefCell<Vec<Lookup>>,
+    /// `.luaurc` aliases visible from this file, read the first time a string require needs them.
+    aliases: Option<BTreeMap<String, Alias>>,
+    /// Every require emitted so far, keyed by its code, for `--hoist-requires`.
+    emitted_requires: HashMap<String, EmittedRequire>,
+    /// Why the import being visited could not be resolved, added to its diagnostic.
+    unresolved_notes: Vec<String>,
 }
 
 impl<'a> TSTransformer<'a> {
-    fn create_findchild_call(&self, path_expression: String) -> Expression {
-        let parts: Vec<&str> = path_expression.split('.').collect();
+    fn record_lookup(&self, lookup: Lookup) {
+        let mut lookups = self.lookups.borrow_mut();
+        if !lookups.contains(&lookup) {
+            lookups.push(lookup);
+        }
+    }
 
-        // Create the base prefix using TokenReference
-        let prefix = Prefix::Name(
-            TokenReference::new(
-                Vec::new(),
-                Token::new(TokenType::Identifier { identifier: parts[0].into() }),
-                Vec::new()
-            )
+    fn roblox_path_of(&self, fs_path: &Path) -> Option<&'a RobloxPath> {
+        self.record_lookup(Lookup::Script(fs_path.to_path_buf()));
+        self.sourcemap_data.roblox_path_of(fs_path)
+    }
+
+    fn class_name_of(&self, roblox_path: &RobloxPath) -> Option<&'a String> {
+        self.record_lookup(Lookup::ClassName(roblox_path.clone()));
+        self.sourcemap_data.class_name_of(roblox_path)
+    }
+
+    fn service_name_of(&self, class_name: &str) -> Option<&'a String> {
+        self.record_lookup(Lookup::ServiceName(class_name.to_string()));
+        self.sourcemap_data.service_name_of(class_name)
+    }
+
+    fn contains(&self, roblox_path: &RobloxPath) -> bool {
+        self.record_lookup(Lookup::Instance(roblox_path.clone()));
+        self.sourcemap_data.contains(roblox_path)
+    }
+
+    fn fs_path_of(&self, roblox_path: &RobloxPath) -> Option<&'a PathBuf> {
+        self.record_lookup(Lookup::File(roblox_path.clone()));
+        self.sourcemap_data.fs_path_of(roblox_path)
+    }
+
+    fn aliases(&mut self) -> &BTreeMap<String, Alias> {
+        let dir = self.current_fs_path.parent().unwrap_or(Path::new(""));
+        if self.aliases.is_none() {
+            self.record_lookup(Lookup::LuauAliases(dir.to_path_buf()));
+        }
+        self.aliases.get_or_insert_with(|| load_aliases(dir, &mut self.diagnostics))
+    }
+
+    fn missing_file_of(&self, roblox_path: &RobloxPath) -> Option<&'a PathBuf> {
+        self.record_lookup(Lookup::MissingFile(roblox_path.clone()));
+        self.sourcemap_data.missing_file_of(roblox_path)
+    }
+
+    /// Checks that `path_expression` points at a ModuleScript whose file exists, following a
+    /// Folder to its `init` ModuleScript, and returns the path to require. Imports from scripts
+    /// the sourcemap knows nothing about cannot be checked and are trusted as before.
+    fn verify_module(&self, require_path: RequirePath) -> Result<RequirePath, UnusableTarget> {
+        let Some(source) = self.roblox_path_of(self.current_fs_path) else {
+            return Ok(require_path);
+        };
+        // Anything missing from the sourcemap would only turn into a nil `FindFirstChild`
+        let roblox_path = match require_path.resolve_from(source) {
+            Some(roblox_path) if self.contains(&roblox_path) => roblox_path,
+            roblox_path => {
+                return Err(UnusableTarget::NotInSourcemap { roblox_path });
+            }
+        };
+
+        let (require_path, roblox_path) = match self.class_name_of(&roblox_path).map(String::as_str) {
+            None | Some("ModuleScript") => (require_path, roblox_path),
+            Some("Folder") if
+                self.class_name_of(&roblox_path.join("init")).map(String::as_str) ==
+                Some("ModuleScript")
+            => {
+                (require_path.join("init"), roblox_path.join("init"))
+            }
+            Some(class_name) => {
+                return Err(UnusableTarget::NotAModule {
+                    class_name: class_name.to_string(),
+                    roblox_path,
+                });
+            }
+        };
+
+        if let Some(fs_path) = self.missing_file_of(&roblox_path) {
+            return Err(UnusableTarget::MissingFile { roblox_path, fs_path: fs_path.clone() });
+        }
+        Ok(require_path)
+    }
+
+    fn record_unusable_target(&mut self, span: Span, expression: String, target: UnusableTarget) {
+        let diagnostic = match target {
+            UnusableTarget::NotAModule { roblox_path, class_name } =>
+                Diagnostic::new(
+                    self.unresolved_severity,
+                    Code::NotAModule,
+                    format!(
+                        "`{}` resolves to `{}`, which is a {}, not a ModuleScript",
+                        expression.trim(),
+                        roblox_path,
+                        class_name
+                    )
+                ),
+            UnusableTarget::MissingFile { roblox_path, fs_path } =>
+                Diagnostic::new(
+                    self.missing_file_severity,
+                    Code::MissingFile,
+                    format!(
+                        "`{}` resolves to `{}`, whose file {} does not exist",
+                        expression.trim(),
+                        roblox_path,
+                        fs_path.display()
+                    )
+                ).with_note("the sourcemap may be out of date"),
+            UnusableTarget::NotInSourcemap { roblox_path } =>
+                Diagnostic::new(
+                    self.unresolved_severity,
+                    Code::UnresolvedImport,
+                    format!("unresolved import `{}`", expression.trim())
+                ).with_note(match roblox_path {
+                    Some(roblox_path) => format!("`{}` is not in the sourcemap", roblox_path),
+                    None => "it climbs above the root of the sourcemap".to_string(),
+                }),
+        };
+        self.diagnostics.push(
+            diagnostic
+                .with_file(self.current_fs_path)
+                .with_span(span)
+                .with_note("the import was left untouched")
         );
+    }
+
+    fn record_unresolved(&mut self, code: Code, span: Span, expression: String) {
+        let message = match code {
+            Code::UnresolvedRuntime => format!(
+                "cannot replace `{}`: the runtime or this script is missing from the sourcemap",
+                expression.trim()
+            ),
+            _ => format!("unresolved import `{}`", expression.trim()),
+        };
+        let mut diagnostic = Diagnostic::new(self.unresolved_severity, code, message)
+            .with_file(self.current_fs_path)
+            .with_span(span);
+        for note in std::mem::take(&mut self.unresolved_notes) {
+            diagnostic = diagnostic.with_note(note);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn record_rewrite(
+        &mut self,
+        span: Span,
+        original: String,
+        resolved_path: &RequirePath,
+        emitted: &Expression
+    ) {
+        let target = self
+            .roblox_path_of(self.current_fs_path)
+            .and_then(|source| resolved_path.resolve_from(source));
+        let package = target
+            .as_ref()
+            .and_then(|target| self.package_of(target))
+            .map(|package_root| package_display_name(&package_root));
+        let emitted = emitted.to_string().trim().to_string();
+        let name = resolved_path.children
+            .last()
+            .or(resolved_path.service.as_ref().map(|service| &service.name))
+            .unwrap_or(&resolved_path.root);
+        self.emitted_requires.insert(emitted.clone(), EmittedRequire {
+            target: target.map_or_else(|| emitted.clone(), |target| target.to_string()),
+            name: name.clone(),
+        });
+        self.rewrites.push(Rewrite {
+            span,
+            original: original.trim().to_string(),
+            resolved_path: resolved_path.to_string(),
+            emitted,
+            package,
+        });
+    }
+
+    fn package_of(&self, roblox_path: &RobloxPath) -> Option<RobloxPath> {
+        self.record_lookup(Lookup::OwningPackage(roblox_path.clone()));
+        self.sourcemap_data.package_of(roblox_path)
+    }
+
+    /// Warns about relative imports into another package's internals. Packages should only be
+    /// imported through `TS.getModule`, which keeps working when the package is moved or deduped.
+    fn check_package_boundary(&mut self, require_path: &RequirePath, span: Span) {
+        let Some(source) = self.roblox_path_of(self.current_fs_path) else {
+            return;
+        };
+        let Some(target) = require_path.resolve_from(source) else {
+            return;
+        };
+        let Some(target_package) = self.package_of(&target) else {
+            return;
+        };
+        if target_package == target || self.package_of(source).as_ref() == Some(&target_package) {
+            return;
+        }
 
-        let mut suffixes: Vec<Suffix> = Vec::new();
+        self.diagnostics.push(
+            Diagnostic::warning(
+                Code::PackageInternals,
+                format!(
+                    "`{}` reaches into the internals of package `{}`",
+                    require_path,
+                    package_display_name(&target_package)
+                )
+            )
+                .with_file(self.current_fs_path)
+                .with_span(span)
+                .with_note("import the package through `TS.getModule` instead")
+        );
+    }
 
-        // Create :FindFirstChild() method calls for each part after the first
-        for part in &parts[1..] {
-            if part == &"Parent" {
+    /// Chains `:<method>("name")` calls for each child, with `timeout` as a second argument when
+    /// given. `.Parent` hops are always plain indexing.
+    fn create_findchild_call(
+        &self,
+        require_path: &RequirePath,
+        method: &str,
+        timeout: Option<f64>
+    ) -> Expression {
+        let (prefix, mut suffixes) = create_require_root(require_path);
+
+        // Create :FindFirstChild() or :WaitForChild() method calls for each child after the parents
+        let parents = std::iter::repeat_n("Parent", require_path.parents).map(|part| (part, true));
+        let children = require_path.children.iter().map(|part| (part.as_str(), false));
+        for (part, is_parent) in parents.chain(children) {
+            if is_parent {
                 // Use direct property access for "Parent"
                 suffixes.push(
                     Suffix::Index(Index::Dot {
@@ -136,55 +852,27 @@ impl<'a> TSTransformer<'a> {
                 );
             } else {
                 // Add method call suffix for :FindFirstChild("part")
-                let method_call = Suffix::Call(
-                    Call::MethodCall(
-                        MethodCall::new(
-                            TokenReference::new(
-                                Vec::new(),
-                                Token::new(TokenType::Identifier {
-                                    identifier: "FindFirstChild".into(),
-                                }),
-                                Vec::new()
-                            ),
-                            FunctionArgs::Parentheses {
-                                parentheses: ContainedSpan::new(
-                                    TokenReference::new(
-                                        Vec::new(),
-                                        Token::new(TokenType::Symbol { symbol: Symbol::LeftParen }),
-                                        Vec::new()
-                                    ),
+                let mut args = Punctuated::new();
+                match timeout {
+                    Some(timeout) => {
+                        args.push(Pair::Punctuated(string_expression(part), comma_token()));
+                        args.push(
+                            Pair::End(
+                                Expression::Number(
                                     TokenReference::new(
                                         Vec::new(),
-                                        Token::new(TokenType::Symbol {
-                                            symbol: Symbol::RightParen,
+                                        Token::new(TokenType::Number {
+                                            text: timeout.to_string().into(),
                                         }),
                                         Vec::new()
                                     )
-                                ),
-                                arguments: {
-                                    let mut args = Punctuated::new();
-                                    args.push(
-                                        Pair::End(
-                                            Expression::String(
-                                                TokenReference::new(
-                                                    Vec::new(),
-                                                    Token::new(TokenType::StringLiteral {
-                                                        literal: (*part).into(),
-                                                        multi_line_depth: 0,
-                                                        quote_type: StringLiteralQuoteType::Double,
-                                                    }),
-                                                    Vec::new()
-                                                )
-                                            )
-                                        )
-                                    );
-                                    args
-                                },
-                            }
-                        )
-                    )
-                );
-                suffixes.push(method_call);
+                                )
+                            )
+                        );
+                    }
+                    None => args.push(Pair::End(string_expression(part))),
+                }
+                suffixes.push(create_method_call(method, args));
             }
         }
 
@@ -194,7 +882,9 @@ impl<'a> TSTransformer<'a> {
                 Var::Name(
                     TokenReference::new(
                         Vec::new(),
-                        Token::new(TokenType::Identifier { identifier: parts[0].into() }),
+                        Token::new(TokenType::Identifier {
+                            identifier: require_path.root.as_str().into(),
+                        }),
                         Vec::new()
                     )
                 )
@@ -206,45 +896,145 @@ impl<'a> TSTransformer<'a> {
         }
     }
 
-    fn create_find_child_require_call(&self, path_expression: String) -> Expression {
-        self.create_require_call_with_expression(self.create_findchild_call(path_expression))
+    fn create_find_child_require_call(&self, require_path: &RequirePath) -> Expression {
+        self.create_require_call_with_expression(
+            self.create_findchild_call(require_path, "FindFirstChild", None)
+        )
     }
 
-    fn create_require_call(&self, path_expression: String) -> Expression {
-        let parts: Vec<&str> = path_expression.split('.').collect();
+    fn create_wait_for_child_require_call(&self, require_path: &RequirePath) -> Expression {
+        self.create_require_call_with_expression(
+            self.create_findchild_call(require_path, "WaitForChild", self.wait_for_child.timeout)
+        )
+    }
 
-        // Create the base prefix using TokenReference
-        let prefix = Prefix::Name(
-            TokenReference::new(
-                Vec::new(),
-                Token::new(TokenType::Identifier { identifier: parts[0].into() }),
-                Vec::new()
-            )
-        );
+    /// `require("...")` with the path from this file to the file behind `require_path`, or
+    /// `None` when the target has no file of its own.
+    fn create_string_require_call(&mut self, require_path: &RequirePath) -> Option<Expression> {
+        let target = self
+            .roblox_path_of(self.current_fs_path)
+            .and_then(|source| require_path.resolve_from(source))?;
+        let target_fs_path = self.fs_path_of(&target)?;
+        let current_fs_path = self.current_fs_path;
+        let path = string_require_path(current_fs_path, target_fs_path, self.aliases())?;
+        Some(self.create_require_call_with_expression(string_expression(&path)))
+    }
 
-        // Create suffixes using bracket notation
-        let suffixes: Vec<Suffix> = parts[1..]
-            .iter()
-            .map(|part| {
-                Suffix::Index(Index::Brackets {
-                    brackets: ContainedSpan::new(
-                        TokenReference::symbol("[").unwrap(),
-                        TokenReference::symbol("]").unwrap()
-                    ),
-                    expression: Expression::String(
-                        TokenReference::new(
+    /// `require_path` started from `game:GetService(...)` when `[emit.get-service]` asks for it,
+    /// which needs the sourcemap to be rooted at a DataModel whose children are services.
+    fn service_rooted(&self, require_path: &RequirePath) -> Option<RequirePath> {
+        let rules = self.get_service;
+        if rules.max_parents.is_none() && !rules.across_services {
+            return None;
+        }
+        let source = self.roblox_path_of(self.current_fs_path)?;
+        let target = require_path.resolve_from(source)?;
+        if self.class_name_of(&target.truncated(1)).map(String::as_str) != Some("DataModel") {
+            return None;
+        }
+        // Anything else directly under the DataModel, such as a Folder, is only reachable by name
+        let class_name = self.class_name_of(&target.truncated(2))?;
+
+        let too_deep = rules.max_parents.is_some_and(|max_parents| require_path.parents > max_parents);
+        let crosses_services =
+            rules.across_services && source.segments().get(1) != target.segments().get(1);
+        if too_deep || crosses_services { RequirePath::from_service(&target, class_name) } else { None }
+    }
+
+    fn create_path_require_call(&mut self, require_path: &RequirePath, span: Span) -> Expression {
+        let emit_style = self.file_emit_style();
+        // String requires are relative to the file, so they never start from a service
+        let service_rooted = match emit_style {
+            EmitStyle::StringRequire => None,
+            _ => self.service_rooted(require_path),
+        };
+        let require_path = service_rooted.as_ref().unwrap_or(require_path);
+
+        match emit_style {
+            EmitStyle::FindFirstChild => self.create_find_child_require_call(require_path),
+            EmitStyle::WaitForChild => self.create_wait_for_child_require_call(require_path),
+            EmitStyle::BracketIndex => self.create_require_call(require_path, false),
+            EmitStyle::DotIndex => self.create_require_call(require_path, true),
+            EmitStyle::StringRequire => {
+                if let Some(require_call) = self.create_string_require_call(require_path) {
+                    return require_call;
+                }
+                self.diagnostics.push(
+                    Diagnostic::warning(
+                        Code::NoStringRequire,
+                        format!("`{}` has no file that can be required by string", require_path)
+                    )
+                        .with_file(self.current_fs_path)
+                        .with_span(span)
+                        .with_note("it is required through FindFirstChild instead")
+                );
+                self.create_find_child_require_call(require_path)
+            }
+        }
+    }
+
+    /// The configured style, unless `[emit.wait-for-child]` asks for `WaitForChild` in this
+    /// script because of where it lives or what kind of script it is.
+    fn file_emit_style(&self) -> EmitStyle {
+        let rules = self.wait_for_child;
+        if rules.paths.is_empty() && rules.script_kinds.is_empty() {
+            return self.emit_style;
+        }
+        let Some(roblox_path) = self.roblox_path_of(self.current_fs_path) else {
+            return self.emit_style;
+        };
+
+        let below_data_model = &roblox_path.segments()[1..];
+        let matches_path = rules.paths.iter().any(|path| {
+            let prefix: Vec<String> = path.split('/').map(String::from).collect();
+            below_data_model.starts_with(&prefix)
+        });
+        let matches_kind = self
+            .class_name_of(roblox_path)
+            .is_some_and(|class_name| rules.script_kinds.contains(class_name));
+
+        if matches_path || matches_kind { EmitStyle::WaitForChild } else { self.emit_style }
+    }
+
+    /// Indexes each step directly: `script["Parent"]["x"]`, or `script.Parent.x` with
+    /// `use_dots` for names that are identifiers. Children sharing a name with a member of
+    /// Instance are still looked up with `:FindFirstChild`, since indexing would return the member.
+    fn create_require_call(&self, require_path: &RequirePath, use_dots: bool) -> Expression {
+        let (prefix, mut suffixes) = create_require_root(require_path);
+
+        let parents = std::iter::repeat_n("Parent", require_path.parents).map(|part| (part, true));
+        let children = require_path.children.iter().map(|part| (part.as_str(), false));
+        suffixes.extend(
+            parents.chain(children).map(|(part, is_parent)| {
+                if !is_parent && is_instance_member(part) {
+                    create_method_call(
+                        "FindFirstChild",
+                        Punctuated::from_iter([Pair::End(string_expression(part))])
+                    )
+                } else if use_dots && is_identifier(part) {
+                    Suffix::Index(Index::Dot {
+                        dot: TokenReference::new(
+                            Vec::new(),
+                            Token::new(TokenType::Symbol { symbol: Symbol::Dot }),
+                            Vec::new()
+                        ),
+                        name: TokenReference::new(
                             Vec::new(),
-                            Token::new(TokenType::StringLiteral {
-                                literal: (*part).into(),
-                                multi_line_depth: 0,
-                                quote_type: StringLiteralQuoteType::Double,
-                            }),
+                            Token::new(TokenType::Identifier { identifier: part.into() }),
                             Vec::new()
-                        )
-                    ),
-                })
+                        ),
+                    })
+                } else {
+                    Suffix::Index(Index::Brackets {
+                        brackets: ContainedSpan::new(
+                            TokenReference::symbol("[").unwrap(),
+                            TokenReference::symbol("]").unwrap()
+                        ),
+                        expression: string_expression(part),
+                    })
+                }
             })
-            .collect();
+        );
 
         let var_expr = VarExpression::new(prefix).with_suffixes(suffixes);
         let path_arg = Expression::Var(Var::Expression(Box::new(var_expr)));
@@ -281,11 +1071,7 @@ impl<'a> TSTransformer<'a> {
                         Token::new(TokenType::Symbol {
                             symbol: Symbol::RightParen,
                         }),
-                        vec![
-                            Token::new(TokenType::Whitespace {
-                                characters: "\n".into(),
-                            })
-                        ]
+                        Vec::new()
                     )
                 ),
                 arguments,
@@ -296,17 +1082,87 @@ impl<'a> TSTransformer<'a> {
     }
 
     fn extract_string_literal(&self, token: &TokenReference) -> String {
-        token
-            .token()
-            .to_string()
-            .trim_matches(|c| (c == '"' || c == '\''))
-            .to_string()
+        match token.token_type() {
+            TokenType::StringLiteral { literal, quote_type: StringLiteralQuoteType::Brackets, .. } => {
+                literal.to_string()
+            }
+            TokenType::StringLiteral { literal, .. } => unescape_string(literal),
+            _ => token.token().to_string(),
+        }
+    }
+
+    /// Reads the instance an import starts from, such as `script`, `script.Parent.Parent` or
+    /// `game:GetService("ReplicatedStorage")`.
+    fn extract_require_root(&self, expression: &Expression) -> Option<RequirePath> {
+        let (root, suffixes): (&TokenReference, Vec<&Suffix>) = match expression {
+            Expression::Var(Var::Name(name)) => (name, Vec::new()),
+            Expression::Var(Var::Expression(var_expr)) => {
+                let Prefix::Name(name) = var_expr.prefix() else {
+                    return None;
+                };
+                (name, var_expr.suffixes().collect())
+            }
+            Expression::FunctionCall(function_call) => {
+                let Prefix::Name(name) = function_call.prefix() else {
+                    return None;
+                };
+                (name, function_call.suffixes().collect())
+            }
+            _ => {
+                return None;
+            }
+        };
+
+        let mut require_path = RequirePath {
+            root: root.token().to_string(),
+            service: None,
+            parents: 0,
+            children: Vec::new(),
+        };
+        let mut suffixes = suffixes.into_iter().peekable();
+        if require_path.root == "game"
+            && let Some(service) = suffixes.peek().and_then(|suffix| self.extract_get_service(suffix))
+        {
+            let name = self.service_name_of(&service).cloned().unwrap_or_else(|| service.clone());
+            require_path.service = Some(Service { name, class_name: service });
+            suffixes.next();
+        }
+        for suffix in suffixes {
+            let Suffix::Index(Index::Dot { name, .. }) = suffix else {
+                return None;
+            };
+            let name = name.token().to_string();
+            if name == "Parent" && require_path.children.is_empty() {
+                require_path.parents += 1;
+            } else {
+                require_path.children.push(name);
+            }
+        }
+        Some(require_path)
+    }
+
+    /// The service named by a `:GetService("...")` call with a single string literal.
+    fn extract_get_service(&self, suffix: &Suffix) -> Option<String> {
+        let Suffix::Call(Call::MethodCall(method_call)) = suffix else {
+            return None;
+        };
+        let FunctionArgs::Parentheses { arguments, .. } = method_call.args() else {
+            return None;
+        };
+        if method_call.name().token().to_string() != "GetService" || arguments.len() != 1 {
+            return None;
+        }
+        let Some(Expression::String(token)) = arguments.iter().next() else {
+            return None;
+        };
+        Some(self.extract_string_literal(token))
     }
 
     fn is_ts_runtime_assignment(&self, expr: &Expression) -> bool {
         matches!(expr, Expression::Var(Var::Expression(var_expr)) if self.is_global_script_access(var_expr))
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
     fn is_global_script_access(&self, var_expr: &VarExpression) -> bool {
         if let Prefix::Name(name) = var_expr.prefix() {
             if name.token().to_string() == "_G" {
@@ -324,6 +1180,7 @@ impl<'a> TSTransformer<'a> {
         false
     }
 
+    #[allow(clippy::collapsible_if)]
     fn get_ts_method_name(&self, call: &FunctionCall) -> Option<String> {
         if let Prefix::Name(name) = call.prefix() {
             if name.token().to_string() == "TS" {
@@ -347,7 +1204,14 @@ impl<'a> TSTransformer<'a> {
         None
     }
 
-    fn translate_literal_path(&self, arguments: &Punctuated<Expression>) -> Option<String> {
+    /// `span` is where the whole import is, for diagnostics about the package it refers to.
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
+    fn translate_literal_path(
+        &mut self,
+        arguments: &Punctuated<Expression>,
+        span: Span
+    ) -> Option<RequirePath> {
+        self.unresolved_notes.clear();
         let first_path_part = arguments.iter().nth(1)?;
 
         // Check if the second argument is a TS.getModule() call
@@ -363,7 +1227,7 @@ impl<'a> TSTransformer<'a> {
                             ),
                         ) = func_call.suffixes().find(|suffix| matches!(suffix, Suffix::Call(_)))
                     {
-                        return self.resolve_getmodule_call_from_args(getmodule_args);
+                        return self.resolve_getmodule_call_from_args(getmodule_args, span);
                     }
                 }
             }
@@ -418,7 +1282,8 @@ impl<'a> TSTransformer<'a> {
                                         }
 
                                         return self.resolve_getmodule_call_from_args(
-                                            &modified_args
+                                            &modified_args,
+                                            span
                                         );
                                     }
                                 }
@@ -430,24 +1295,25 @@ impl<'a> TSTransformer<'a> {
         }
 
         // Fallback to original logic for simple string literals
-        let mut path_parts = Vec::new();
-        path_parts.push(first_path_part.to_string());
+        let mut require_path = self.extract_require_root(first_path_part)?;
         for arg in arguments.iter().skip(2) {
             if let Expression::String(token) = arg {
-                path_parts.push(self.extract_string_literal(token));
+                require_path.children.push(self.extract_string_literal(token));
             } else {
                 return None;
             }
         }
-        Some(path_parts.join("."))
+        self.check_package_boundary(&require_path, span);
+        Some(require_path)
     }
 
     fn resolve_getmodule_call_from_args(
-        &self,
-        arguments: &Punctuated<Expression>
-    ) -> Option<String> {
+        &mut self,
+        arguments: &Punctuated<Expression>,
+        span: Span
+    ) -> Option<RequirePath> {
         // Extract arguments from TS.getModule(script, "@rbxts", "package-name")
-        let mut module_path_parts = Vec::new();
+        let mut module_path_parts = vec!["node_modules".to_string()];
 
         // Skip first argument (script), extract remaining string arguments
         for arg in arguments.iter().skip(1) {
@@ -458,57 +1324,160 @@ impl<'a> TSTransformer<'a> {
             }
         }
 
-        if module_path_parts.is_empty() {
+        if module_path_parts.len() == 1 {
             return None;
         }
 
-        // Build the node_modules path: node_modules.@rbxts.package-name
-        let node_modules_path = format!("node_modules.{}", module_path_parts.join("."));
+        // The package lives at node_modules/@rbxts/package-name
+        let source_roblox_path = self.roblox_path_of(self.current_fs_path)?;
+        let package_name = package_name(&module_path_parts[1..]);
+        let pin = self.package_pins.get(&package_name).map(PathBuf::as_path);
+        let Some(target_roblox_path) = self.find_package_in_sourcemap(
+            source_roblox_path,
+            &module_path_parts,
+            pin
+        ) else {
+            // Copies elsewhere in the game would not be found by `TS.getModule` either
+            let unreachable: Vec<String> = self.sourcemap_data
+                .package_candidates(&module_path_parts)
+                .into_iter()
+                .map(|candidate| {
+                    format!(
+                        "found {}, which is not in a node_modules above this script",
+                        self.describe_package(candidate)
+                    )
+                })
+                .collect();
+            self.unresolved_notes = unreachable;
+            return None;
+        };
+        self.check_package_ambiguity(
+            &package_name,
+            source_roblox_path,
+            &module_path_parts,
+            target_roblox_path,
+            pin,
+            span
+        );
+        Some(RequirePath::between(source_roblox_path, target_roblox_path))
+    }
 
-        if let Some(target_roblox_path) = self.find_package_in_sourcemap(&node_modules_path) {
-            if
-                let Some(source_roblox_path) = self.sourcemap_data.fs_to_roblox.get(
-                    self.current_fs_path
-                )
-            {
-                return Some(roblox_path_to_luau_require(source_roblox_path, &target_roblox_path));
-            }
+    fn find_package_in_sourcemap(
+        &self,
+        importer: &RobloxPath,
+        package_path: &[String],
+        pin: Option<&Path>
+    ) -> Option<&'a RobloxPath> {
+        self.record_lookup(Lookup::Package {
+            importer: importer.clone(),
+            path: package_path.to_vec(),
+            pin: pin.map(Path::to_path_buf),
+        });
+        self.sourcemap_data.find_package(importer, package_path, pin)
+    }
+
+    /// A package candidate with the first of its files, for diagnostics.
+    fn describe_package(&self, candidate: &RobloxPath) -> String {
+        match self.sourcemap_data.files_under(candidate).next() {
+            Some(fs_path) => format!("`{}` ({})", candidate, fs_path.display()),
+            None => format!("`{}` (no files)", candidate),
         }
-        None
     }
 
-    fn find_package_in_sourcemap(&self, package_path: &str) -> Option<String> {
-        for (roblox_path, _fs_path) in &self.sourcemap_data.roblox_to_fs {
-            if roblox_path.ends_with(&package_path) {
-                return Some(roblox_path.clone());
-            }
+    /// Warns when `importer` can reach more than one instance matching `package_path` and no pin
+    /// singles one out, since the nearest copy is not necessarily the one that was meant. Copies
+    /// it cannot reach do not count, as `TS.getModule` would never pick them either.
+    fn check_package_ambiguity(
+        &mut self,
+        package_name: &str,
+        importer: &RobloxPath,
+        package_path: &[String],
+        chosen: &RobloxPath,
+        pin: Option<&Path>,
+        span: Span
+    ) {
+        let candidates = self.sourcemap_data.reachable_candidates(importer, package_path);
+        let is_pinned = pin.is_some_and(|pin| self.sourcemap_data.is_pinned(chosen, pin));
+        if candidates.len() < 2 || is_pinned {
+            return;
         }
-        None
+
+        let mut diagnostic = Diagnostic::warning(
+            Code::AmbiguousPackage,
+            format!("`{}` matches {} instances this script can reach", package_name, candidates.len())
+        )
+            .with_file(self.current_fs_path)
+            .with_span(span)
+            .with_note(format!("using {}", self.describe_package(chosen)));
+        for candidate in candidates.iter().filter(|candidate| **candidate != chosen) {
+            diagnostic = diagnostic.with_note(format!("also found {}", self.describe_package(candidate)));
+        }
+        diagnostic = diagnostic.with_note(match pin {
+            Some(_) => format!("the pin for `{}` matches none of them", package_name),
+            None => format!("pin one under [packages.pin] in {}", config::CONFIG_FILE_NAME),
+        });
+        self.diagnostics.push(diagnostic);
     }
 }
 
 impl<'a> VisitorMut for TSTransformer<'a> {
+    #[allow(clippy::collapsible_if)]
     fn visit_local_assignment(&mut self, node: LocalAssignment) -> LocalAssignment {
         if let Some(first_expr) = node.expressions().iter().next() {
             if self.is_ts_runtime_assignment(first_expr) {
                 if
                     let (Some(source_roblox), Some(target_roblox)) = (
-                        self.sourcemap_data.fs_to_roblox.get(self.current_fs_path),
-                        self.sourcemap_data.fs_to_roblox.get(self.runtime_fs_path),
+                        self.roblox_path_of(self.current_fs_path),
+                        self.roblox_path_of(self.runtime_fs_path),
                     )
                 {
-                    let path_str = roblox_path_to_luau_require(source_roblox, target_roblox);
-                    let require_expr = self.create_find_child_require_call(path_str);
+                    let require_path = match
+                        self.verify_module(RequirePath::between(source_roblox, target_roblox))
+                    {
+                        Ok(require_path) => require_path,
+                        Err(target) => {
+                            self.record_unusable_target(
+                                Span::of(first_expr),
+                                first_expr.to_string(),
+                                target
+                            );
+                            return node;
+                        }
+                    };
+                    let require_expr = self.create_path_require_call(
+                        &require_path,
+                        Span::of(first_expr)
+                    );
+
+                    self.record_rewrite(
+                        Span::of(first_expr),
+                        first_expr.to_string(),
+                        &require_path,
+                        &require_expr
+                    );
+                    let (leading_trivia, trailing_trivia) = surrounding_trivia(first_expr);
+                    let require_expr = with_surrounding_trivia(
+                        require_expr,
+                        leading_trivia,
+                        trailing_trivia
+                    );
 
                     return node.with_expressions(
                         Punctuated::from_iter(vec![Pair::End(require_expr)])
                     );
                 }
+
+                self.record_unresolved(
+                    Code::UnresolvedRuntime,
+                    Span::of(first_expr),
+                    first_expr.to_string()
+                );
             }
         }
         node
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match)]
     fn visit_function_call(&mut self, node: FunctionCall) -> FunctionCall {
         if let Some(method_name) = self.get_ts_method_name(&node) {
             if method_name == "import" {
@@ -522,14 +1491,37 @@ impl<'a> VisitorMut for TSTransformer<'a> {
                             ast::Call::AnonymousCall(FunctionArgs::Parentheses { arguments, .. }),
                         ) = call_suffix
                     {
-                        let path_str = self.translate_literal_path(arguments);
-
-                        if let Some(path) = path_str {
-                            if
-                                let Expression::FunctionCall(fc) =
-                                    self.create_find_child_require_call(path)
-                            {
-                                return fc;
+                        let path_str = self.translate_literal_path(arguments, Span::of(&node));
+
+                        match path_str.map(|path| self.verify_module(path)) {
+                            Some(Ok(path)) => {
+                                let require_expr = self.create_path_require_call(&path, Span::of(&node));
+                                self.record_rewrite(
+                                    Span::of(&node),
+                                    node.to_string(),
+                                    &path,
+                                    &require_expr
+                                );
+                                let (leading_trivia, trailing_trivia) = surrounding_trivia(&node);
+                                let require_expr = with_surrounding_trivia(
+                                    require_expr,
+                                    leading_trivia,
+                                    trailing_trivia
+                                );
+
+                                if let Expression::FunctionCall(fc) = require_expr {
+                                    return fc;
+                                }
+                            }
+                            Some(Err(target)) => {
+                                self.record_unusable_target(Span::of(&node), node.to_string(), target);
+                            }
+                            None => {
+                                self.record_unresolved(
+                                    Code::UnresolvedImport,
+                                    Span::of(&node),
+                                    node.to_string()
+                                );
                             }
                         }
                     }
@@ -539,6 +1531,7 @@ impl<'a> VisitorMut for TSTransformer<'a> {
         node
     }
 
+    #[allow(clippy::collapsible_if, clippy::collapsible_match, clippy::single_match)]
     fn visit_var_expression(&mut self, node: VarExpression) -> VarExpression {
         match node.clone().prefix() {
             Prefix::Name(name_token) => {
@@ -560,12 +1553,40 @@ impl<'a> VisitorMut for TSTransformer<'a> {
                                         FunctionArgs::Parentheses { arguments, .. },
                                     ) = call
                                 {
-                                    let path_str = self.translate_literal_path(arguments);
+                                    let path_str = self.translate_literal_path(
+                                        arguments,
+                                        Span::between(name_token, suffixes[1])
+                                    );
                                     if let Some(path) = path_str {
-                                        if
-                                            let Expression::FunctionCall(fc) =
-                                                self.create_find_child_require_call(path)
-                                        {
+                                        let path = match self.verify_module(path) {
+                                            Ok(path) => path,
+                                            Err(target) => {
+                                                self.record_unusable_target(
+                                                    Span::between(name_token, suffixes[1]),
+                                                    format!("{}{}{}", name_token, suffixes[0], suffixes[1]),
+                                                    target
+                                                );
+                                                return node;
+                                            }
+                                        };
+                                        let require_expr = self.create_path_require_call(
+                                            &path,
+                                            Span::between(name_token, suffixes[1])
+                                        );
+                                        self.record_rewrite(
+                                            Span::between(name_token, suffixes[1]),
+                                            format!("{}{}{}", name_token, suffixes[0], suffixes[1]),
+                                            &path,
+                                            &require_expr
+                                        );
+                                        // The member access after the call keeps its own trivia
+                                        let require_expr = with_surrounding_trivia(
+                                            require_expr,
+                                            name_token.leading_trivia().cloned().collect(),
+                                            surrounding_trivia(suffixes[1]).1
+                                        );
+
+                                        if let Expression::FunctionCall(fc) = require_expr {
                                             // Preserve only the suffixes AFTER the TS.import() call
                                             let remaining_suffixes: Vec<Suffix> = suffixes[2..]
                                                 .iter()
@@ -579,6 +1600,13 @@ impl<'a> VisitorMut for TSTransformer<'a> {
                                             ).with_suffixes(remaining_suffixes);
                                         }
                                     }
+
+                                    // Only report the `TS.import(...)` part, not the trailing member access
+                                    self.record_unresolved(
+                                        Code::UnresolvedImport,
+                                        Span::between(name_token, suffixes[1]),
+                                        format!("{}{}{}", name_token, suffixes[0], suffixes[1])
+                                    );
                                 }
                             }
                         }
@@ -592,96 +1620,944 @@ impl<'a> VisitorMut for TSTransformer<'a> {
     }
 }
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
-        eprintln!(
-            "Usage: {} <transform_directory> <path/to/sourcemap.json> <path/to/runtime.luau>",
-            args[0]
+/// Copies a file or directory that the transformer leaves untouched into the output tree.
+fn mirror_entry(entry: &walkdir::DirEntry, output_path: &Path) -> Result<(), Diagnostic> {
+    let result = if entry.file_type().is_dir() {
+        fs::create_dir_all(output_path)
+    } else if entry.path().is_file() {
+        output_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::copy(entry.path(), output_path).map(|_| ()))
+    } else {
+        return Err(
+            Diagnostic::warning(Code::FileWrite, "not copied to the output directory")
+                .with_file(entry.path())
+                .with_note("only regular files and directories are mirrored")
         );
-        std::process::exit(1);
+    };
+
+    result.map_err(|err| {
+        Diagnostic::error(Code::FileWrite, format!("failed to copy to {}: {}", output_path.display(), err))
+            .with_file(entry.path())
+    })
+}
+
+/// One rewrite in the `--report` file, tagged with the file it happened in.
+#[derive(Serialize)]
+struct ReportEntry {
+    file: String,
+    #[serde(flatten)]
+    rewrite: Rewrite,
+}
+
+#[derive(Serialize)]
+struct Report {
+    rewrites: Vec<ReportEntry>,
+}
+
+fn render_unified_diff(relative_path: &str, original: &str, transformed: &str) -> String {
+    let diff = TextDiff::from_lines(original, transformed);
+    diff
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", relative_path), &format!("b/{}", relative_path))
+        .to_string()
+}
+
+/// Everything a worker needs to process files, shared immutably between all workers.
+struct Run<'a> {
+    settings: &'a Settings,
+    sourcemap_data: &'a SourcemapData,
+    runtime_path: &'a Path,
+    dry_run: bool,
+    check: bool,
+    /// The previous run's cache; only present for incremental runs that write files.
+    cache: Option<&'a Cache>,
+}
+
+/// A file found under the transform directory, queued for a worker.
+struct FileTask {
+    entry: walkdir::DirEntry,
+    relative_path: PathBuf,
+    output_path: Option<PathBuf>,
+}
+
+/// What processing a single file produced. Outcomes are printed in traversal order regardless of
+/// which worker finished first, so the log is the same for any `--jobs` value.
+#[derive(Default)]
+struct FileOutcome {
+    diagnostics: Vec<Diagnostic>,
+    rewrites: Vec<ReportEntry>,
+    diff: Option<String>,
+    written_to: Option<PathBuf>,
+    /// Set instead of `written_to` when the cache showed the existing output is still current.
+    up_to_date: Option<PathBuf>,
+    cache_entry: Option<(String, CacheEntry)>,
+}
+
+fn process_file(run: &Run, task: &FileTask) -> FileOutcome {
+    let mut outcome = FileOutcome::default();
+    let mirror = |outcome: &mut FileOutcome| {
+        if let Some(output_path) = &task.output_path
+            && let Err(diagnostic) = mirror_entry(&task.entry, output_path)
+        {
+            outcome.diagnostics.push(diagnostic);
+        }
+    };
+
+    if !run.settings.file_filter.matches(&task.relative_path) {
+        mirror(&mut outcome);
+        return outcome;
     }
 
-    let transform_path = dunce::canonicalize(Path::new(&args[1]))?;
-    let sourcemap_path = dunce::canonicalize(Path::new(&args[2]))?;
-    let runtime_path = dunce::canonicalize(Path::new(&args[3]))?;
-    let base_dir = sourcemap_path
+    let read = dunce
+        ::canonicalize(task.entry.path())
+        .and_then(|file_path| fs::read_to_string(&file_path).map(|code| (file_path, code)));
+    let (file_path, code) = match read {
+        Ok(read) => read,
+        Err(err) => {
+            outcome.diagnostics.push(
+                Diagnostic::error(Code::FileRead, format!("cannot read file: {}", err))
+                    .with_file(task.entry.path())
+            );
+            return outcome;
+        }
+    };
+
+    // println!("Processing: {}", file_path.display());
+
+    if !code.contains(&run.settings.prefilter) {
+        // println!("  -> Skipping: Does not contain the prefilter");
+        mirror(&mut outcome);
+        return outcome;
+    }
+
+    let report_file = task.relative_path.to_string_lossy().replace('\\', "/");
+    let write_path = task.output_path.as_deref().unwrap_or(&file_path);
+    let input_hash = cache::hash_bytes(code.as_bytes());
+
+    if let Some(entry) = run.cache.and_then(|cache| cache.files.get(&report_file))
+        && entry.is_fresh(&input_hash, write_path, run.sourcemap_data)
+    {
+        outcome.diagnostics = entry.diagnostics.clone();
+        outcome.rewrites = entry.rewrites
+            .iter()
+            .map(|rewrite| ReportEntry { file: report_file.clone(), rewrite: rewrite.clone() })
+            .collect();
+        outcome.up_to_date = Some(write_path.to_path_buf());
+        outcome.cache_entry = Some((report_file, entry.clone()));
+        return outcome;
+    }
+
+    let ast_result = parse_fallible(&code, LuaVersion::luau());
+    if !ast_result.errors().is_empty() {
+        outcome.diagnostics.extend(
+            ast_result.errors().iter().map(|error| {
+                let (start, end) = error.range();
+                Diagnostic::error(Code::ParseError, error.error_message())
+                    .with_file(&file_path)
+                    .with_span(Span { start: start.into(), end: end.into() })
+                    .with_note("the file was left untransformed")
+            })
+        );
+        mirror(&mut outcome);
+        return outcome;
+    }
+
+    // Imports are always resolved against the source file, since that is what the
+    // sourcemap describes; only the destination of the write changes with --out-dir
+    let mut transformer = TSTransformer {
+        current_fs_path: &file_path,
+        sourcemap_data: run.sourcemap_data,
+        runtime_fs_path: run.runtime_path,
+        emit_style: run.settings.emit_style,
+        wait_for_child: &run.settings.wait_for_child,
+        get_service: &run.settings.get_service,
+        package_pins: &run.settings.package_pins,
+        unresolved_severity: if run.check { Severity::Error } else { Severity::Warning },
+        missing_file_severity: if run.check || run.settings.strict_sourcemap {
+            Severity::Error
+        } else {
+            Severity::Warning
+        },
+        diagnostics: Vec::new(),
+        rewrites: Vec::new(),
+        lookups: RefCell::new(Vec::new()),
+        aliases: None,
+        emitted_requires: HashMap::new(),
+        unresolved_notes: Vec::new(),
+    };
+    let mut transformed_ast = transformer.visit_ast(ast_result.ast().clone());
+    if run.settings.hoist_requires {
+        transformed_ast = hoist::hoist_requires(transformed_ast, &transformer.emitted_requires);
+    }
+    let transformed_code = transformed_ast.to_string();
+
+    let lookups = transformer.lookups.take();
+    let cache_entry = CacheEntry {
+        input_hash,
+        output_hash: cache::hash_bytes(transformed_code.as_bytes()),
+        lookups_hash: cache::hash_lookups(&lookups, run.sourcemap_data),
+        lookups,
+        diagnostics: transformer.diagnostics.clone(),
+        rewrites: transformer.rewrites.clone(),
+    };
+
+    outcome.diagnostics.append(&mut transformer.diagnostics);
+    outcome.rewrites.extend(
+        transformer.rewrites.drain(..).map(|rewrite| ReportEntry {
+            file: report_file.clone(),
+            rewrite,
+        })
+    );
+
+    if run.dry_run || run.check {
+        if run.dry_run && transformed_code != code {
+            outcome.diff = Some(render_unified_diff(&report_file, &code, &transformed_code));
+        }
+        return outcome;
+    }
+
+    let written = write_path
         .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
-
-    // --- Spawn a new thread with a larger stack to run the main logic ---
-    let builder = thread::Builder
-        ::new()
-        .name("parser_thread".into())
-        .stack_size(8 * 1024 * 1024); // 8MB stack
-
-    let handle = builder
-        .spawn(move || {
-            let mut maps = SourcemapData {
-                roblox_to_fs: HashMap::new(),
-                fs_to_roblox: HashMap::new(),
-                fs_projects: Vec::new(),
-            };
-            let sourcemap_content = fs
-                ::read_to_string(sourcemap_path)
-                .expect("Failed to read sourcemap file");
-            let sourcemap_root: SourcemapNode = serde_json
-                ::from_str(&sourcemap_content)
-                .expect("Failed to parse sourcemap JSON");
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(write_path, transformed_code));
+    match written {
+        Ok(()) => {
+            outcome.written_to = Some(write_path.to_path_buf());
+            outcome.cache_entry = Some((report_file, cache_entry));
+        }
+        Err(err) => {
+            outcome.diagnostics.push(
+                Diagnostic::error(Code::FileWrite, format!("cannot write file: {}", err))
+                    .with_file(write_path)
+            );
+        }
+    }
+    outcome
+}
+
+/// Paths that stay fixed for the whole run, canonicalized up front.
+struct Inputs {
+    transform_path: PathBuf,
+    sourcemap_path: PathBuf,
+    /// Sourcemaps merged into the main one, with their paths and base directories canonicalized.
+    extra_sourcemaps: Vec<ExtraSourcemap>,
+    runtime_path: PathBuf,
+    out_path: Option<PathBuf>,
+}
+
+impl Inputs {
+    /// The main sourcemap followed by every sourcemap merged into it.
+    fn sourcemaps(&self) -> Vec<ExtraSourcemap> {
+        let main_sourcemap = ExtraSourcemap {
+            path: self.sourcemap_path.clone(),
+            base_dir: None,
+            mount: Vec::new(),
+        };
+        std::iter::once(main_sourcemap).chain(self.extra_sourcemaps.iter().cloned()).collect()
+    }
+}
+
+fn resolve_inputs(settings: &Settings, emitter: &mut Emitter) -> Option<Inputs> {
+    let canonicalize_input = |path: &Path, what: &str| {
+        dunce::canonicalize(path).map_err(|err| {
+            Diagnostic::error(Code::InvalidInput, format!("cannot open {}: {}", what, err)).with_file(path)
+        })
+    };
+    let inputs = (
+        canonicalize_input(&settings.transform_directory, "transform directory"),
+        canonicalize_input(&settings.sourcemap, "sourcemap"),
+        if settings.lint_sourcemap {
+            Ok(settings.runtime.clone())
+        } else {
+            canonicalize_input(&settings.runtime, "runtime")
+        },
+    );
+    let (transform_path, sourcemap_path, runtime_path) = match inputs {
+        (Ok(transform_path), Ok(sourcemap_path), Ok(runtime_path)) =>
+            (transform_path, sourcemap_path, runtime_path),
+        (transform_path, sourcemap_path, runtime_path) => {
+            emitter.emit_all(
+                [transform_path.err(), sourcemap_path.err(), runtime_path.err()].into_iter().flatten()
+            );
+            return None;
+        }
+    };
+
+    let mut extra_sourcemaps = Vec::new();
+    for extra in &settings.extra_sourcemaps {
+        let path = canonicalize_input(&extra.path, "merged sourcemap");
+        let base_dir = extra.base_dir
+            .as_deref()
+            .map(|base_dir| canonicalize_input(base_dir, "base directory of merged sourcemap"))
+            .transpose();
+        match (path, base_dir) {
+            (Ok(path), Ok(base_dir)) => {
+                extra_sourcemaps.push(ExtraSourcemap { path, base_dir, mount: extra.mount.clone() });
+            }
+            (path, base_dir) => {
+                emitter.emit_all([path.err(), base_dir.err()].into_iter().flatten());
+                return None;
+            }
+        }
+    }
+
+    let out_path = match &settings.out_dir {
+        Some(out_dir) if !settings.dry_run && !settings.check => {
+            let out_path = fs::create_dir_all(out_dir).and_then(|_| dunce::canonicalize(out_dir));
+            match out_path {
+                Ok(out_path) if out_path == transform_path => {
+                    emitter.emit(
+                        Diagnostic::error(
+                            Code::InvalidInput,
+                            "--out-dir must not be the transform directory itself"
+                        ).with_file(&out_path)
+                    );
+                    return None;
+                }
+                Ok(out_path) => Some(out_path),
+                Err(err) => {
+                    emitter.emit(
+                        Diagnostic::error(
+                            Code::FileWrite,
+                            format!("cannot create output directory: {}", err)
+                        ).with_file(out_dir)
+                    );
+                    return None;
+                }
+            }
+        }
+        Some(out_dir) => {
+            emitter.emit(
+                Diagnostic::new(
+                    Severity::Note,
+                    Code::InvalidInput,
+                    "--out-dir is ignored because nothing is written in --dry-run or --check mode"
+                ).with_file(out_dir)
+            );
+            None
+        }
+        None => None,
+    };
+
+    Some(Inputs { transform_path, sourcemap_path, extra_sourcemaps, runtime_path, out_path })
+}
+
+/// Rojo project files are read directly, anything else is expected to be the output of
+/// `rojo sourcemap`.
+fn is_project_file(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".project.json")
+}
+
+/// Problems found while reading a project point at the project file they came from, everything
+/// else at the sourcemap itself.
+fn with_default_file(diagnostic: Diagnostic, path: &Path) -> Diagnostic {
+    if diagnostic.file.is_some() { diagnostic } else { diagnostic.with_file(path) }
+}
+
+/// Reads the tree of a sourcemap, or of a project file standing in for one.
+fn read_sourcemap(
+    sourcemap_path: &Path,
+    diagnostics: &mut Vec<Diagnostic>
+) -> Result<SourcemapNode, Diagnostic> {
+    if is_project_file(sourcemap_path) {
+        return project::load_project(sourcemap_path, diagnostics);
+    }
+    fs::read_to_string(sourcemap_path)
+        .map_err(|err| {
+            Diagnostic::error(Code::SourcemapRead, format!("cannot read sourcemap: {}", err))
+        })
+        .and_then(|content| {
+            serde_json::from_str::<SourcemapNode>(&content).map_err(|err| {
+                Diagnostic::error(Code::SourcemapParse, format!("invalid sourcemap: {}", err))
+            })
+        })
+}
+
+/// Renames `root` after its mount point, or after the DataModel when it has none, and returns the
+/// path of the instance it is placed under.
+fn mount_root(
+    root: &mut SourcemapNode,
+    mount: &[String],
+    data_model: &RobloxPath
+) -> Option<RobloxPath> {
+    let Some((name, ancestors)) = mount.split_last() else {
+        root.name = data_model.segments()[0].clone();
+        return None;
+    };
+    root.name = name.clone();
+    Some(ancestors.iter().fold(data_model.clone(), |parent, ancestor| parent.join(ancestor)))
+}
+
+/// Builds the path maps from the main sourcemap and merges every extra sourcemap into them. An
+/// extra sourcemap's root replaces the instance at its mount point, or is merged into the
+/// DataModel when it has none.
+fn load_sourcemap(
+    settings: &Settings,
+    inputs: &Inputs,
+    emitter: &mut Emitter
+) -> Option<SourcemapData> {
+    let mut maps = SourcemapData {
+        roblox_to_fs: BTreeMap::new(),
+        fs_to_roblox: HashMap::new(),
+        class_names: HashMap::new(),
+        paths_by_name: HashMap::new(),
+        package_roots: HashSet::new(),
+        missing_files: HashMap::new(),
+        service_names: HashMap::new(),
+    };
+
+    let mut data_model: Option<RobloxPath> = None;
+    for sourcemap in inputs.sourcemaps() {
+        let sourcemap_path = &sourcemap.path;
+        let base_dir = sourcemap.base_dir
+            .clone()
+            .or_else(|| sourcemap_path.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let mut sourcemap_diagnostics = Vec::new();
+        let mut sourcemap_root = match read_sourcemap(sourcemap_path, &mut sourcemap_diagnostics) {
+            Ok(root) => root,
+            Err(diagnostic) => {
+                emitter.emit_all(sourcemap_diagnostics);
+                emitter.emit(with_default_file(diagnostic, sourcemap_path));
+                return None;
+            }
+        };
+
+        // The main sourcemap's root stands for the DataModel every other sourcemap is placed in
+        let is_main = data_model.is_none();
+        let data_model = data_model.get_or_insert_with(|| RobloxPath::root(&sourcemap_root.name));
+        let parent = mount_root(&mut sourcemap_root, &sourcemap.mount, data_model);
+
+        let was_package_root = parent
+            .as_ref()
+            .is_some_and(|parent| maps.package_roots.contains(&parent.join(&sourcemap_root.name)));
+        if parent.is_none() && !is_main {
+            // Only the main sourcemap says what the DataModel itself is, so a sourcemap merged
+            // into it just adds children
+            for child in &sourcemap_root.children {
+                build_path_maps(
+                    child,
+                    &mut maps,
+                    Some(data_model),
+                    base_dir.as_path(),
+                    &mut sourcemap_diagnostics
+                );
+            }
+        } else {
+            build_path_maps(
+                &sourcemap_root,
+                &mut maps,
+                parent.as_ref(),
+                base_dir.as_path(),
+                &mut sourcemap_diagnostics
+            );
+        }
+        // A mounted project is part of the game, not a package nested inside it
+        if let Some(parent) = &parent
+            && !was_package_root
+        {
+            maps.package_roots.remove(&parent.join(&sourcemap_root.name));
+        }
 
-            build_path_maps(&sourcemap_root, &mut maps, "", base_dir.as_path());
+        emitter.emit_all(
+            sourcemap_diagnostics.into_iter().map(|mut diagnostic| {
+                if settings.strict_sourcemap && diagnostic.code == Code::SourcemapPathMissing {
+                    diagnostic.severity = Severity::Error;
+                }
+                with_default_file(diagnostic, sourcemap_path)
+            })
+        );
+    }
 
+    // In dry-run mode stdout is reserved for the diff so it can be piped into a patch
+    if settings.diagnostics_level >= DiagnosticsLevel::Note {
+        if settings.dry_run {
+            eprintln!("Successfully built path maps with {} entries.", maps.roblox_to_fs.len());
+        } else {
             println!("Successfully built path maps with {} entries.", maps.roblox_to_fs.len());
+        }
+    }
 
-            for entry in WalkDir::new(&transform_path)
-                .into_iter()
-                .filter_map(Result::ok)
-                .filter(|e| {
-                    let ext = e
-                        .path()
-                        .extension()
-                        .map(|ext| {
-                            ext.to_str()
-                                .map(|s| s.to_lowercase())
-                                .unwrap_or_default()
-                        });
-                    ext == Some("luau".to_string()) || ext == Some("lua".to_string())
-                }) {
-                let file_path = dunce
-                    ::canonicalize(entry.path())
-                    .expect("Failed to canonicalize path");
-
-                // println!("Processing: {}", file_path.display());
-
-                let code = fs::read_to_string(&file_path).expect("Failed to read file");
-                if !code.contains("local TS = _G[script]") {
-                    // println!("  -> Skipping: Does not contain 'local TS = _G[script]'");
-                    continue;
+    Some(maps)
+}
+
+/// Walks `root`, which is the transform directory or something inside it, mirroring directories
+/// into the output tree and returning every file found.
+fn collect_tasks(root: &Path, inputs: &Inputs, emitter: &mut Emitter) -> Vec<FileTask> {
+    // Symlinked package folders are only followed when writing to a separate tree, so an
+    // in-place run can never rewrite files inside a shared package store
+    let mut tasks = Vec::new();
+    let mut walker = WalkDir::new(root)
+        .follow_links(inputs.out_path.is_some())
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                let mut diagnostic = Diagnostic::error(Code::FileRead, err.to_string());
+                if let Some(path) = err.path() {
+                    diagnostic = diagnostic.with_file(path);
                 }
+                emitter.emit(diagnostic);
+                continue;
+            }
+        };
+        let relative_path = entry
+            .path()
+            .strip_prefix(&inputs.transform_path)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+        let output_path = inputs.out_path.as_ref().map(|out| out.join(&relative_path));
+
+        // Never descend into the output tree when it lives inside the transform directory
+        if inputs.out_path.as_deref() == Some(entry.path()) {
+            walker.skip_current_dir();
+            continue;
+        }
 
-                let ast_result = parse_fallible(&code, LuaVersion::luau());
-                if !ast_result.errors().is_empty() {
-                    eprintln!("  -> Skipped due to parse errors: {:?}", ast_result.errors());
-                    continue;
+        if entry.file_type().is_dir() {
+            if let Some(output_path) = &output_path
+                && let Err(diagnostic) = mirror_entry(&entry, output_path)
+            {
+                emitter.emit(diagnostic);
+            }
+            continue;
+        }
+
+        tasks.push(FileTask { entry, relative_path, output_path });
+    }
+    tasks
+}
+
+/// Runs `tasks` on the worker pool, emitting their results in order and recording them in
+/// `report` and `next_cache`.
+fn process_tasks(
+    run: &Run,
+    tasks: &[FileTask],
+    emitter: &mut Emitter,
+    report: &mut Report,
+    next_cache: &mut Cache
+) {
+    let level = run.settings.diagnostics_level;
+    let next_task = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        // Each worker gets the large stack the parser needs for deeply nested roblox-ts output
+        for worker in 0..run.settings.jobs.min(tasks.len().max(1)) {
+            let sender = sender.clone();
+            let next_task = &next_task;
+            thread::Builder
+                ::new()
+                .name(format!("parser_thread_{}", worker))
+                .stack_size(run.settings.parser_stack_size)
+                .spawn_scoped(scope, move || {
+                    loop {
+                        let index = next_task.fetch_add(1, Ordering::Relaxed);
+                        let Some(task) = tasks.get(index) else {
+                            break;
+                        };
+                        if sender.send((index, process_file(run, task))).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("Failed to spawn worker thread");
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        for (index, outcome) in receiver {
+            pending.insert(index, outcome);
+
+            while let Some(outcome) = pending.remove(&next_index) {
+                let FileOutcome { diagnostics, rewrites, diff, written_to, up_to_date, cache_entry } =
+                    outcome;
+                emitter.emit_all(diagnostics);
+                report.rewrites.extend(rewrites);
+                if let Some(diff) = diff {
+                    print!("{}", diff);
+                }
+                if let Some(written_to) = written_to
+                    && level >= DiagnosticsLevel::Note
+                {
+                    println!("{} -> Transformed successfully.", written_to.display());
+                }
+                if let Some(up_to_date) = up_to_date
+                    && level >= DiagnosticsLevel::Note
+                {
+                    println!("{} -> Up to date.", up_to_date.display());
                 }
 
-                let mut transformer = TSTransformer {
-                    current_fs_path: &file_path,
-                    sourcemap_data: &maps,
-                    runtime_fs_path: &runtime_path,
-                };
-                let transformed_ast = transformer.visit_ast(ast_result.ast().clone());
-                fs::write(&file_path, transformed_ast.to_string()).expect(
-                    "Failed to write transformed file"
-                );
-                println!("{} -> Transformed successfully.", file_path.display());
+                let file = tasks[next_index].relative_path.to_string_lossy().replace('\\', "/");
+                match cache_entry {
+                    Some((file, entry)) => {
+                        next_cache.files.insert(file, entry);
+                    }
+                    None => {
+                        next_cache.files.remove(&file);
+                    }
+                }
+                next_index += 1;
             }
-        })
-        .unwrap();
+        }
+    });
+}
+
+/// Hash of everything outside the individual files that affects the output. A cache written with
+/// a different fingerprint is discarded as a whole.
+fn cache_fingerprint(settings: &Settings, inputs: &Inputs) -> String {
+    cache::hash_bytes(
+        format!(
+            "{}|{}|{:?}|{:?}|{:?}|{}|{}|{}|{}",
+            env!("CARGO_PKG_VERSION"),
+            inputs.runtime_path.display(),
+            settings.emit_style,
+            settings.wait_for_child,
+            settings.get_service,
+            settings.strict_sourcemap,
+            settings.hoist_requires,
+            settings.prefilter,
+            inputs.out_path.as_deref().unwrap_or(&inputs.transform_path).display()
+        ).as_bytes()
+    )
+}
+
+fn save_cache(cache: &Cache, cache_path: &Path, emitter: &mut Emitter) {
+    if let Err(err) = cache.save(cache_path) {
+        emitter.emit(
+            Diagnostic::warning(Code::FileWrite, format!("cannot write cache: {}", err))
+                .with_file(cache_path)
+        );
+    }
+}
 
-    // Wait for the new thread to finish
-    handle.join().unwrap();
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = match cli::parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("Usage: {} {}", args[0], cli::USAGE);
+            std::process::exit(1);
+        }
+    };
+    let settings = match Settings::resolve(options) {
+        Ok(settings) => settings,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    };
 
-    Ok(())
+    let mut emitter = Emitter::new(settings.diagnostics_format, settings.diagnostics_level);
+    let Some(inputs) = resolve_inputs(&settings, &mut emitter) else {
+        std::process::exit(emitter.finish());
+    };
+    let Some(maps) = load_sourcemap(&settings, &inputs, &mut emitter) else {
+        std::process::exit(emitter.finish());
+    };
+    if settings.lint_sourcemap {
+        lint::lint_sourcemap(&settings, &inputs, &maps, &mut emitter);
+        std::process::exit(emitter.finish());
+    }
+
+    // The cache only describes files as they were written, so it is neither read nor updated by
+    // runs that do not write
+    let cache_path = settings.cache.as_ref().filter(|_| !settings.dry_run && !settings.check);
+    let fingerprint = cache_fingerprint(&settings, &inputs);
+    let previous_cache = cache_path.map(|path| Cache::load(path, &fingerprint));
+    let mut next_cache = Cache::new(fingerprint);
+
+    let run = Run {
+        settings: &settings,
+        sourcemap_data: &maps,
+        runtime_path: &inputs.runtime_path,
+        dry_run: settings.dry_run,
+        check: settings.check,
+        cache: previous_cache.as_ref(),
+    };
+    let tasks = collect_tasks(&inputs.transform_path, &inputs, &mut emitter);
+    let mut report = Report { rewrites: Vec::new() };
+    process_tasks(&run, &tasks, &mut emitter, &mut report, &mut next_cache);
+
+    if let Some(cache_path) = cache_path {
+        save_cache(&next_cache, cache_path, &mut emitter);
+    }
+
+    if let Some(report_path) = &settings.report {
+        let report_json = serde_json::to_string_pretty(&report).expect("Failed to serialize report");
+        if let Err(err) = fs::write(report_path, report_json) {
+            emitter.emit(
+                Diagnostic::error(Code::FileWrite, format!("cannot write report: {}", err))
+                    .with_file(report_path)
+            );
+        }
+    }
+
+    if settings.watch {
+        emitter.finish();
+        watch::watch(&settings, &inputs, maps, next_cache);
+    }
+
+    std::process::exit(emitter.finish());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCEMAP: &str = r#"{
+        "name": "Game", "className": "DataModel", "children": [
+            { "name": "ReplicatedStorage", "className": "ReplicatedStorage", "children": [
+                { "name": "Shared", "className": "Folder", "children": [
+                    { "name": "util", "className": "ModuleScript", "filePaths": ["shared/util.luau"] }
+                ] }
+            ] },
+            { "name": "ServerScriptService", "className": "ServerScriptService", "children": [
+                { "name": "main", "className": "Script", "filePaths": ["server/main.server.luau"] }
+            ] },
+            { "name": "Lib", "className": "Folder", "children": [
+                { "name": "lib", "className": "ModuleScript", "filePaths": ["lib/lib.luau"] }
+            ] }
+        ]
+    }"#;
+
+    const ACROSS_SERVICES: GetServiceConfig = GetServiceConfig { max_parents: None, across_services: true };
+
+    /// The data of sourcemap `json`, with every file it lists taken to exist at the path given.
+    fn sourcemap_data(json: &str) -> SourcemapData {
+        let root: SourcemapNode = serde_json::from_str(json).unwrap();
+        let mut maps = SourcemapData {
+            roblox_to_fs: BTreeMap::new(),
+            fs_to_roblox: HashMap::new(),
+            class_names: HashMap::new(),
+            paths_by_name: HashMap::new(),
+            package_roots: HashSet::new(),
+            missing_files: HashMap::new(),
+            service_names: HashMap::new(),
+        };
+        build_path_maps(&root, &mut maps, None, Path::new(""), &mut Vec::new());
+        for (roblox_path, fs_path) in std::mem::take(&mut maps.missing_files) {
+            maps.roblox_to_fs.insert(roblox_path.clone(), fs_path.clone());
+            maps.fs_to_roblox.insert(fs_path, roblox_path);
+        }
+        maps
+    }
+
+    fn transform(maps: &SourcemapData, file: &str, code: &str, get_service: &GetServiceConfig) -> String {
+        transform_with_diagnostics(maps, file, code, get_service).0
+    }
+
+    fn transform_with_diagnostics(
+        maps: &SourcemapData,
+        file: &str,
+        code: &str,
+        get_service: &GetServiceConfig
+    ) -> (String, Vec<Diagnostic>) {
+        let mut transformer = TSTransformer {
+            current_fs_path: Path::new(file),
+            sourcemap_data: maps,
+            runtime_fs_path: Path::new("include/RuntimeLib.lua"),
+            emit_style: EmitStyle::FindFirstChild,
+            wait_for_child: &WaitForChildConfig::default(),
+            get_service,
+            package_pins: &BTreeMap::new(),
+            unresolved_severity: Severity::Warning,
+            missing_file_severity: Severity::Warning,
+            diagnostics: Vec::new(),
+            rewrites: Vec::new(),
+            lookups: RefCell::new(Vec::new()),
+            aliases: None,
+            emitted_requires: HashMap::new(),
+            unresolved_notes: Vec::new(),
+        };
+        let ast = parse_fallible(code, LuaVersion::luau()).into_ast();
+        let transformed = transformer.visit_ast(ast).to_string();
+        (transformed, transformer.diagnostics)
+    }
+
+    #[test]
+    fn rewrites_imports_rooted_at_a_service() {
+        let maps = sourcemap_data(SOURCEMAP);
+        let code =
+            "local util = TS.import(script, game:GetService(\"ReplicatedStorage\"), \"Shared\", \"util\")\n";
+        assert_eq!(
+            transform(&maps, "server/main.server.luau", code, &GetServiceConfig::default()),
+            "local util = require(game:GetService(\"ReplicatedStorage\"):FindFirstChild(\"Shared\"):FindFirstChild(\"util\"))\n"
+        );
+    }
+
+    #[test]
+    fn gets_services_by_class() {
+        let maps = sourcemap_data(&SOURCEMAP.replace(
+            r#""name": "ReplicatedStorage", "className""#,
+            r#""name": "RS", "className""#
+        ));
+        let code = "local util = TS.import(script, script.Parent.Parent, \"RS\", \"Shared\", \"util\")\n";
+        let expected =
+            "local util = require(game:GetService(\"ReplicatedStorage\"):FindFirstChild(\"Shared\"):FindFirstChild(\"util\"))\n";
+        assert_eq!(transform(&maps, "server/main.server.luau", code, &ACROSS_SERVICES), expected);
+
+        // And the other way around, roblox-ts's own imports find the instance by its class
+        let code =
+            "local util = TS.import(script, game:GetService(\"ReplicatedStorage\"), \"Shared\", \"util\")\n";
+        assert_eq!(transform(&maps, "server/main.server.luau", code, &ACROSS_SERVICES), expected);
+    }
+
+    #[test]
+    fn keeps_folders_under_the_data_model_relative() {
+        let maps = sourcemap_data(SOURCEMAP);
+        let code = "local lib = TS.import(script, script.Parent.Parent, \"Lib\", \"lib\")\n";
+        assert_eq!(
+            transform(&maps, "server/main.server.luau", code, &ACROSS_SERVICES),
+            "local lib = require(script.Parent.Parent:FindFirstChild(\"Lib\"):FindFirstChild(\"lib\"))\n"
+        );
+    }
+
+    const PACKAGES: &str = r#"{
+        "name": "Game", "className": "DataModel", "children": [
+            { "name": "ReplicatedStorage", "className": "ReplicatedStorage", "children": [
+                { "name": "pkg", "className": "Folder", "children": [
+                    { "name": "node_modules", "className": "Folder", "children": [
+                        { "name": "@rbxts", "className": "Folder", "children": [
+                            { "name": "t", "className": "ModuleScript",
+                              "filePaths": ["pkg/t/default.project.json", "pkg/t/init.luau"] }
+                        ] }
+                    ] },
+                    { "name": "main", "className": "ModuleScript", "filePaths": ["pkg/main.luau"] }
+                ] },
+                { "name": "node_modules", "className": "Folder", "children": [
+                    { "name": "@rbxts", "className": "Folder", "children": [
+                        { "name": "t", "className": "ModuleScript",
+                          "filePaths": ["t/default.project.json", "t/init.luau"] }
+                    ] }
+                ] },
+                { "name": "main", "className": "ModuleScript", "filePaths": ["main.luau"] }
+            ] },
+            { "name": "ServerStorage", "className": "ServerStorage", "children": [
+                { "name": "node_modules", "className": "Folder", "children": [
+                    { "name": "@rbxts", "className": "Folder", "children": [
+                        { "name": "only", "className": "ModuleScript",
+                          "filePaths": ["only/default.project.json", "only/init.luau"] }
+                    ] }
+                ] }
+            ] }
+        ]
+    }"#;
+
+    fn roblox_path(path: &str) -> RobloxPath {
+        let mut segments = path.split('/');
+        let root = RobloxPath::root(segments.next().unwrap());
+        segments.fold(root, |path, segment| path.join(segment))
+    }
+
+    fn package_path(name: &str) -> Vec<String> {
+        ["node_modules", "@rbxts", name].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn package_candidates_are_sorted() {
+        let maps = sourcemap_data(PACKAGES);
+        let candidates: Vec<String> = maps
+            .package_candidates(&package_path("t"))
+            .iter()
+            .map(|candidate| candidate.to_string())
+            .collect();
+        assert_eq!(candidates, [
+            "Game.ReplicatedStorage.node_modules[\"@rbxts\"].t",
+            "Game.ReplicatedStorage.pkg.node_modules[\"@rbxts\"].t",
+        ]);
+    }
+
+    #[test]
+    fn nearest_package_wins() {
+        let maps = sourcemap_data(PACKAGES);
+        let find = |importer: &str| maps.find_package(&roblox_path(importer), &package_path("t"), None);
+        assert_eq!(
+            find("Game/ReplicatedStorage/pkg/main"),
+            Some(&roblox_path("Game/ReplicatedStorage/pkg/node_modules/@rbxts/t"))
+        );
+        assert_eq!(
+            find("Game/ReplicatedStorage/main"),
+            Some(&roblox_path("Game/ReplicatedStorage/node_modules/@rbxts/t"))
+        );
+    }
+
+    #[test]
+    fn unreachable_packages_are_not_found() {
+        let maps = sourcemap_data(PACKAGES);
+        let importer = roblox_path("Game/ReplicatedStorage/main");
+        assert_eq!(maps.package_candidates(&package_path("only")).len(), 1);
+        assert_eq!(maps.find_package(&importer, &package_path("only"), None), None);
+    }
+
+    fn package_diagnostics(file: &str, name: &str) -> Vec<Diagnostic> {
+        let code = format!("local {name} = TS.import(script, TS.getModule(script, \"@rbxts\", \"{name}\"))\n");
+        transform_with_diagnostics(&sourcemap_data(PACKAGES), file, &code, &GetServiceConfig::default()).1
+    }
+
+    #[test]
+    fn only_reachable_copies_are_ambiguous() {
+        let diagnostics = package_diagnostics("pkg/main.luau", "t");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Code::AmbiguousPackage);
+        assert!(diagnostics[0].notes[0].starts_with("using `Game.ReplicatedStorage.pkg.node_modules"));
+
+        // The copy inside `pkg` is out of reach from outside of it
+        assert!(package_diagnostics("main.luau", "t").is_empty());
+    }
+
+    #[test]
+    fn unreachable_copies_are_pointed_out() {
+        let diagnostics = package_diagnostics("main.luau", "only");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Code::UnresolvedImport);
+        assert_eq!(diagnostics[0].notes, [
+            "found `Game.ServerStorage.node_modules[\"@rbxts\"].only` (only/init.luau), which is not in a node_modules above this script",
+        ]);
+    }
+
+    const UTIL: &str = "require(script.Parent.Parent:FindFirstChild(\"ReplicatedStorage\"):FindFirstChild(\"Shared\"):FindFirstChild(\"util\"))";
+
+    fn transform_import(code: &str) -> String {
+        let code = code.replace(
+            "IMPORT",
+            "TS.import(script, script.Parent.Parent, \"ReplicatedStorage\", \"Shared\", \"util\")"
+        );
+        transform(&sourcemap_data(SOURCEMAP), "server/main.server.luau", &code, &GetServiceConfig::default())
+    }
+
+    #[test]
+    fn keeps_members_of_imports_on_the_same_line() {
+        assert_eq!(transform_import("local foo = IMPORT.foo\n"), format!("local foo = {UTIL}.foo\n"));
+    }
+
+    #[test]
+    fn keeps_comments_around_imports() {
+        assert_eq!(
+            transform_import("local util = IMPORT -- comment\n"),
+            format!("local util = {UTIL} -- comment\n")
+        );
+        assert_eq!(
+            transform_import("local util = --[[c]] IMPORT\n"),
+            format!("local util = --[[c]] {UTIL}\n")
+        );
+    }
+
+    #[test]
+    fn keeps_the_line_break_after_the_closing_parenthesis() {
+        // `tokens()` lists the parentheses before the arguments, so the last token it yields is
+        // `"util"` rather than the `)` carrying the line break
+        assert_eq!(
+            transform_import("local util = IMPORT\nlocal x = 1\n"),
+            format!("local util = {UTIL}\nlocal x = 1\n")
+        );
+    }
 }
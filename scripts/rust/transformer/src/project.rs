@@ -0,0 +1,427 @@
+use globset::GlobSet;
+use serde::Deserialize;
+use std::{ collections::BTreeMap, fs, path::{ Path, PathBuf } };
+
+use crate::{ config::build_glob_set, diagnostics::{ Code, Diagnostic }, SourcemapNode };
+
+/// File suffixes Rojo turns into instances and the class they become, longest first so
+/// `.server.luau` wins over `.luau`. Model files can contain any class, which is left unknown.
+const INSTANCE_SUFFIXES: [(&str, &str); 14] = [
+    (".server.luau", "Script"),
+    (".server.lua", "Script"),
+    (".client.luau", "LocalScript"),
+    (".client.lua", "LocalScript"),
+    (".project.json", ""),
+    (".model.json", ""),
+    (".luau", "ModuleScript"),
+    (".lua", "ModuleScript"),
+    (".json", "ModuleScript"),
+    (".toml", "ModuleScript"),
+    (".txt", "StringValue"),
+    (".csv", "LocalizationTable"),
+    (".rbxmx", ""),
+    (".rbxm", ""),
+];
+
+/// Files that turn the directory containing them into a script instead of a Folder.
+const INIT_SCRIPTS: [(&str, &str); 6] = [
+    ("init.luau", "ModuleScript"),
+    ("init.lua", "ModuleScript"),
+    ("init.server.luau", "Script"),
+    ("init.server.lua", "Script"),
+    ("init.client.luau", "LocalScript"),
+    ("init.client.lua", "LocalScript"),
+];
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Project {
+    name: String,
+    tree: ProjectNode,
+    #[serde(default)]
+    glob_ignore_paths: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ProjectPath {
+    Required(PathBuf),
+    Optional {
+        optional: PathBuf,
+    },
+}
+
+/// A node of a project's `tree`. `$`-prefixed keys describe the node itself, every other key is a
+/// child instance.
+#[derive(Deserialize, Debug)]
+struct ProjectNode {
+    #[serde(rename = "$className")]
+    class_name: Option<String>,
+    #[serde(rename = "$path")]
+    path: Option<ProjectPath>,
+    #[serde(flatten)]
+    entries: BTreeMap<String, serde_json::Value>,
+}
+
+/// Builds the same tree `rojo sourcemap` prints, straight from a `*.project.json`. File paths are
+/// absolute, which `build_path_maps` accepts just like the relative ones Rojo writes.
+pub fn load_project(
+    project_path: &Path,
+    diagnostics: &mut Vec<Diagnostic>
+) -> Result<SourcemapNode, Diagnostic> {
+    let mut reader = ProjectReader { ignore_rules: Vec::new(), diagnostics };
+    reader.read_project(project_path, None)
+}
+
+struct ProjectReader<'a> {
+    /// `globIgnorePaths` of every project currently being read, with the directory they are
+    /// relative to. Nested projects add to the rules of the projects containing them.
+    ignore_rules: Vec<(PathBuf, GlobSet)>,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl ProjectReader<'_> {
+    fn read_project(
+        &mut self,
+        project_path: &Path,
+        name: Option<&str>
+    ) -> Result<SourcemapNode, Diagnostic> {
+        let project: Project = fs
+            ::read_to_string(project_path)
+            .map_err(|err| {
+                Diagnostic::error(Code::SourcemapRead, format!("cannot read project: {}", err))
+            })
+            .and_then(|content| {
+                serde_json::from_str(&content).map_err(|err| {
+                    Diagnostic::error(Code::SourcemapParse, format!("invalid project: {}", err))
+                })
+            })
+            .map_err(|diagnostic| diagnostic.with_file(project_path))?;
+
+        let project_dir = project_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let ignore_rules = build_glob_set(&project.glob_ignore_paths).map_err(|err| {
+            Diagnostic::error(Code::SourcemapParse, err).with_file(project_path)
+        })?;
+        self.ignore_rules.push((project_dir.clone(), ignore_rules));
+
+        let mut node = self.read_tree_node(
+            name.unwrap_or(&project.name),
+            &project.tree,
+            None,
+            &project_dir,
+            project_path
+        );
+        node.file_paths.push(path_string(project_path));
+
+        self.ignore_rules.pop();
+        Ok(node)
+    }
+
+    fn read_tree_node(
+        &mut self,
+        name: &str,
+        tree_node: &ProjectNode,
+        parent_class_name: Option<&str>,
+        project_dir: &Path,
+        project_path: &Path
+    ) -> SourcemapNode {
+        let mut node = match &tree_node.path {
+            Some(ProjectPath::Required(path)) | Some(ProjectPath::Optional { optional: path }) => {
+                let fs_path = project_dir.join(path);
+                match self.read_path(name, &fs_path) {
+                    Some(node) => node,
+                    None if matches!(tree_node.path, Some(ProjectPath::Optional { .. })) => {
+                        empty_node(name, "Folder")
+                    }
+                    // A missing file is kept like `rojo sourcemap` would list it, so
+                    // `build_path_maps` records the instance as missing
+                    None => match missing_file_node(name, &fs_path) {
+                        Some(node) => node,
+                        None => {
+                            self.diagnostics.push(
+                                Diagnostic::warning(
+                                    Code::SourcemapPathMissing,
+                                    format!("`$path` of `{}` does not exist: {}", name, fs_path.display())
+                                ).with_file(project_path)
+                            );
+                            empty_node(name, "Folder")
+                        }
+                    }
+                }
+            }
+            // Services directly under the DataModel are named after their class
+            None if parent_class_name == Some("DataModel") => empty_node(name, name),
+            None => empty_node(name, "Folder"),
+        };
+        if let Some(class_name) = &tree_node.class_name
+            && node.class_name == "Folder"
+        {
+            node.class_name = class_name.clone();
+        }
+
+        for (child_name, child) in &tree_node.entries {
+            if child_name.starts_with('$') {
+                continue;
+            }
+            match serde_json::from_value::<ProjectNode>(child.clone()) {
+                Ok(child) => {
+                    let parent_class_name = Some(node.class_name.as_str());
+                    let child = self.read_tree_node(
+                        child_name,
+                        &child,
+                        parent_class_name,
+                        project_dir,
+                        project_path
+                    );
+                    node.children.push(child);
+                }
+                Err(err) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(
+                            Code::SourcemapParse,
+                            format!("invalid project node `{}`: {}", child_name, err)
+                        ).with_file(project_path)
+                    );
+                }
+            }
+        }
+
+        node
+    }
+
+    /// Reads whatever `path` points at into an instance called `name`, or `None` if it does not
+    /// exist.
+    fn read_path(&mut self, name: &str, path: &Path) -> Option<SourcemapNode> {
+        if path.is_dir() {
+            let nested_project = path.join("default.project.json");
+            if nested_project.is_file() {
+                return self.read_nested_project(name, &nested_project);
+            }
+            Some(self.read_directory(name, path))
+        } else if path.is_file() {
+            if path_string(path).ends_with(".project.json") {
+                return self.read_nested_project(name, path);
+            }
+            let mut node = self.read_file(path)?;
+            node.name = name.to_string();
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    fn read_nested_project(&mut self, name: &str, project_path: &Path) -> Option<SourcemapNode> {
+        match self.read_project(project_path, Some(name)) {
+            Ok(node) => Some(node),
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                Some(empty_node(name, ""))
+            }
+        }
+    }
+
+    fn read_directory(&mut self, name: &str, dir: &Path) -> SourcemapNode {
+        let mut node = empty_node(name, "Folder");
+
+        let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+            Err(err) => {
+                self.diagnostics.push(
+                    Diagnostic::error(Code::FileRead, format!("cannot read directory: {}", err))
+                        .with_file(dir)
+                );
+                return node;
+            }
+        };
+        entries.sort();
+
+        let init_script = INIT_SCRIPTS.iter()
+            .map(|(init, class_name)| (dir.join(init), class_name))
+            .find(|(init, _)| init.is_file());
+        if let Some((init_script, class_name)) = init_script {
+            node.file_paths.push(path_string(&init_script));
+            node.class_name = class_name.to_string();
+        }
+        let init_meta = dir.join("init.meta.json");
+        if init_meta.is_file() {
+            node.file_paths.push(path_string(&init_meta));
+        }
+
+        for entry in entries {
+            if self.is_ignored(&entry) {
+                continue;
+            }
+            let file_name = entry.file_name().map(|name| name.to_string_lossy().to_string());
+            let Some(file_name) = file_name else {
+                continue;
+            };
+
+            if entry.is_dir() {
+                if let Some(child) = self.read_path(&file_name, &entry) {
+                    node.children.push(child);
+                }
+            } else if !file_name.starts_with("init.")
+                && let Some(child) = self.read_file(&entry)
+            {
+                node.children.push(child);
+            }
+        }
+
+        node
+    }
+
+    /// Reads a file found inside a directory, named after the file without its Rojo suffix.
+    fn read_file(&mut self, path: &Path) -> Option<SourcemapNode> {
+        let file_name = path.file_name()?.to_string_lossy().to_string();
+        if file_name.ends_with(".meta.json") {
+            return None;
+        }
+        let (suffix, class_name) = INSTANCE_SUFFIXES.iter().find(|(suffix, _)| {
+            file_name.ends_with(suffix)
+        })?;
+        let name = &file_name[..file_name.len() - suffix.len()];
+
+        if *suffix == ".project.json" {
+            return self.read_nested_project(name, path);
+        }
+
+        let mut node = empty_node(name, class_name);
+        node.file_paths.push(path_string(path));
+        let meta = path.with_file_name(format!("{}.meta.json", name));
+        if meta.is_file() {
+            node.file_paths.push(path_string(&meta));
+        }
+        Some(node)
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore_rules.iter().any(|(base, rules)| {
+            path.strip_prefix(base).is_ok_and(|relative| rules.is_match(relative))
+        })
+    }
+}
+
+fn empty_node(name: &str, class_name: &str) -> SourcemapNode {
+    SourcemapNode {
+        name: name.to_string(),
+        class_name: class_name.to_string(),
+        children: Vec::new(),
+        file_paths: Vec::new(),
+    }
+}
+
+/// The node a file that does not exist would have become, judging by its suffix alone.
+fn missing_file_node(name: &str, path: &Path) -> Option<SourcemapNode> {
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    if file_name.ends_with(".project.json") {
+        return None;
+    }
+    let (_, class_name) = INSTANCE_SUFFIXES.iter().find(|(suffix, _)| {
+        file_name.ends_with(suffix)
+    })?;
+    let mut node = empty_node(name, class_name);
+    node.file_paths.push(path_string(path));
+    Some(node)
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, content: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn load(root: &Path) -> SourcemapNode {
+        let mut diagnostics = Vec::new();
+        let node = load_project(&root.join("default.project.json"), &mut diagnostics).unwrap();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        node
+    }
+
+    fn child<'a>(node: &'a SourcemapNode, name: &str) -> &'a SourcemapNode {
+        node.children.iter().find(|child| child.name == name).unwrap()
+    }
+
+    fn names(node: &SourcemapNode) -> Vec<&str> {
+        node.children.iter().map(|child| child.name.as_str()).collect()
+    }
+
+    const PROJECT: &str = r#"{
+        "name": "game",
+        "tree": {
+            "$className": "DataModel",
+            "ReplicatedStorage": { "$className": "ReplicatedStorage", "$path": "src" }
+        }
+    }"#;
+
+    #[test]
+    fn init_scripts_turn_folders_into_scripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(root, "default.project.json", PROJECT);
+        write(root, "src/util/init.luau", "");
+        write(root, "src/util/helper.luau", "");
+        write(root, "src/main.server.luau", "");
+
+        let game = load(root);
+        assert_eq!(game.class_name, "DataModel");
+        let storage = child(&game, "ReplicatedStorage");
+        assert_eq!(storage.class_name, "ReplicatedStorage");
+        assert_eq!(names(storage), ["main", "util"]);
+        assert_eq!(child(storage, "main").class_name, "Script");
+
+        let util = child(storage, "util");
+        assert_eq!(util.class_name, "ModuleScript");
+        assert_eq!(util.file_paths, [path_string(&root.join("src/util/init.luau"))]);
+        assert_eq!(names(util), ["helper"]);
+        assert_eq!(child(util, "helper").file_paths, [path_string(&root.join("src/util/helper.luau"))]);
+    }
+
+    #[test]
+    fn nested_projects_are_named_by_where_they_are_mounted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(root, "default.project.json", PROJECT);
+        write(root, "src/pkg/default.project.json", r#"{ "name": "package", "tree": { "$path": "lib" } }"#);
+        write(root, "src/pkg/lib/init.luau", "");
+        write(root, "src/pkg/lib/inner.luau", "");
+
+        let game = load(root);
+        let storage = child(&game, "ReplicatedStorage");
+        let pkg = child(storage, "pkg");
+        assert_eq!(pkg.class_name, "ModuleScript");
+        assert_eq!(pkg.file_paths, [
+            path_string(&root.join("src/pkg/lib/init.luau")),
+            path_string(&root.join("src/pkg/default.project.json")),
+        ]);
+        assert_eq!(names(pkg), ["inner"]);
+    }
+
+    #[test]
+    fn glob_ignore_paths_skip_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write(root, "default.project.json", &PROJECT.replace(
+            r#""name": "game","#,
+            r#""name": "game", "globIgnorePaths": ["src/**/*.spec.luau"],"#
+        ));
+        write(root, "src/a.luau", "");
+        write(root, "src/a.spec.luau", "");
+        write(root, "src/nested/b.spec.luau", "");
+
+        let game = load(root);
+        let storage = child(&game, "ReplicatedStorage");
+        assert_eq!(names(storage), ["a", "nested"]);
+        assert!(child(storage, "nested").children.is_empty());
+    }
+}
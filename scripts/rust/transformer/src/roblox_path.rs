@@ -0,0 +1,448 @@
+use serde::{ Deserialize, Serialize };
+use std::fmt;
+
+/// Words that cannot be used after a `.` in Luau, so names equal to them are displayed with
+/// brackets.
+const KEYWORDS: [&str; 22] = [
+    "and",
+    "break",
+    "continue",
+    "do",
+    "else",
+    "elseif",
+    "end",
+    "export",
+    "false",
+    "for",
+    "function",
+    "if",
+    "in",
+    "local",
+    "nil",
+    "not",
+    "or",
+    "repeat",
+    "return",
+    "then",
+    "true",
+    "until",
+];
+
+/// Members every Instance has. A child with one of these names is shadowed by the member when it
+/// is indexed, so it can only be reached with `FindFirstChild`.
+const INSTANCE_MEMBERS: [&str; 38] = [
+    "AddTag",
+    "AncestryChanged",
+    "Archivable",
+    "AttributeChanged",
+    "ChildAdded",
+    "ChildRemoved",
+    "ClassName",
+    "ClearAllChildren",
+    "Clone",
+    "DescendantAdded",
+    "DescendantRemoving",
+    "Destroy",
+    "Destroying",
+    "FindFirstAncestor",
+    "FindFirstAncestorOfClass",
+    "FindFirstAncestorWhichIsA",
+    "FindFirstChild",
+    "FindFirstChildOfClass",
+    "FindFirstChildWhichIsA",
+    "FindFirstDescendant",
+    "GetActor",
+    "GetAttribute",
+    "GetAttributeChangedSignal",
+    "GetAttributes",
+    "GetChildren",
+    "GetDescendants",
+    "GetFullName",
+    "GetPropertyChangedSignal",
+    "GetTags",
+    "HasTag",
+    "IsA",
+    "IsAncestorOf",
+    "IsDescendantOf",
+    "Name",
+    "Parent",
+    "RemoveTag",
+    "SetAttribute",
+    "WaitForChild",
+];
+
+/// Classes `game:GetService` can find, as long as the instance is a direct child of the
+/// DataModel. Rojo places only these there besides plain Folders and models.
+const SERVICES: [&str; 17] = [
+    "Chat",
+    "Lighting",
+    "LocalizationService",
+    "MaterialService",
+    "Players",
+    "ReplicatedFirst",
+    "ReplicatedStorage",
+    "ServerScriptService",
+    "ServerStorage",
+    "SoundService",
+    "StarterGui",
+    "StarterPack",
+    "StarterPlayer",
+    "Teams",
+    "TestService",
+    "TextChatService",
+    "Workspace",
+];
+
+/// The absolute path of an instance in the sourcemap, one segment per instance name. Names are
+/// kept verbatim, so they may contain dots, quotes or anything else Roblox allows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct RobloxPath(Vec<String>);
+
+impl RobloxPath {
+    pub fn root(name: &str) -> RobloxPath {
+        RobloxPath(vec![name.to_string()])
+    }
+
+    pub fn join(&self, name: &str) -> RobloxPath {
+        let mut segments = self.0.clone();
+        segments.push(name.to_string());
+        RobloxPath(segments)
+    }
+
+    /// The ancestor made of the first `len` segments, or the path itself if it is shorter.
+    pub fn truncated(&self, len: usize) -> RobloxPath {
+        RobloxPath(self.0[..len.min(self.0.len())].to_vec())
+    }
+
+    pub fn segments(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn starts_with(&self, segments: &[String]) -> bool {
+        self.0.starts_with(segments)
+    }
+
+    pub fn ends_with(&self, segments: &[String]) -> bool {
+        self.0.ends_with(segments)
+    }
+}
+
+impl fmt::Display for RobloxPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut segments = self.0.iter();
+        if let Some(root) = segments.next() {
+            write_segment(f, root, true)?;
+        }
+        segments.try_for_each(|segment| write_segment(f, segment, false))
+    }
+}
+
+/// A path relative to `root` (normally `script`) in the shape roblox-ts imports use: a number of
+/// `.Parent` steps followed by the names of the children to descend into. Paths rooted at
+/// `game:GetService(...)` name the service and never climb.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequirePath {
+    pub root: String,
+    pub service: Option<Service>,
+    pub parents: usize,
+    pub children: Vec<String>,
+}
+
+/// A service a path starts from. `GetService` finds it by class, while the sourcemap knows it by
+/// the name of the instance, and the two need not be the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub name: String,
+    pub class_name: String,
+}
+
+impl RequirePath {
+    /// The shortest path from the script at `source` to `target`.
+    pub fn between(source: &RobloxPath, target: &RobloxPath) -> RequirePath {
+        let common_len = source.0
+            .iter()
+            .zip(&target.0)
+            .take_while(|(source, target)| source == target)
+            .count();
+
+        RequirePath {
+            root: "script".to_string(),
+            service: None,
+            parents: source.0.len() - common_len,
+            children: target.0[common_len..].to_vec(),
+        }
+    }
+
+    /// `target` reached through `game:GetService(...)`, given the class of the child of the
+    /// DataModel it lies inside. None when that child is not a service.
+    pub fn from_service(target: &RobloxPath, class_name: &str) -> Option<RequirePath> {
+        if !is_service(class_name) {
+            return None;
+        }
+        Some(RequirePath {
+            root: "game".to_string(),
+            service: Some(Service {
+                name: target.0.get(1)?.clone(),
+                class_name: class_name.to_string(),
+            }),
+            parents: 0,
+            children: target.0[2..].to_vec(),
+        })
+    }
+
+    pub fn join(&self, name: &str) -> RequirePath {
+        let mut path = self.clone();
+        path.children.push(name.to_string());
+        path
+    }
+
+    /// The absolute path this points at when required from the script at `source`, if it is
+    /// rooted at `script` or a service and does not climb above the root of the sourcemap.
+    pub fn resolve_from(&self, source: &RobloxPath) -> Option<RobloxPath> {
+        if let Some(service) = &self.service {
+            let mut segments = vec![source.0.first()?.clone(), service.name.clone()];
+            segments.extend(self.children.iter().cloned());
+            return Some(RobloxPath(segments));
+        }
+        if self.root != "script" || self.parents >= source.0.len() {
+            return None;
+        }
+        let mut segments = source.0[..source.0.len() - self.parents].to_vec();
+        segments.extend(self.children.iter().cloned());
+        Some(RobloxPath(segments))
+    }
+}
+
+impl fmt::Display for RequirePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.root)?;
+        if let Some(service) = &self.service {
+            write!(f, ":GetService(\"{}\")", escape_string(&service.class_name))?;
+        }
+        for _ in 0..self.parents {
+            f.write_str(".Parent")?;
+        }
+        self.children.iter().try_for_each(|child| write_segment(f, child, false))
+    }
+}
+
+/// Writes `name` as `.name` when it is a valid identifier and as `["name"]` otherwise, so the
+/// displayed path is unambiguous.
+fn write_segment(f: &mut fmt::Formatter<'_>, name: &str, is_root: bool) -> fmt::Result {
+    if is_identifier(name) {
+        if !is_root {
+            f.write_str(".")?;
+        }
+        f.write_str(name)
+    } else {
+        write!(f, "[\"{}\"]", escape_string(name))
+    }
+}
+
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_') &&
+        !KEYWORDS.contains(&name)
+}
+
+pub fn is_instance_member(name: &str) -> bool {
+    INSTANCE_MEMBERS.contains(&name)
+}
+
+pub fn is_service(class_name: &str) -> bool {
+    SERVICES.contains(&class_name)
+}
+
+/// Escapes `value` for use between double quotes in a Luau string literal.
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            // Always three digits, so a digit following the escape is not read as part of it
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\{:03}", c as u8)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decodes the escape sequences in the contents of a quoted Luau string literal.
+pub fn unescape_string(literal: &str) -> String {
+    let bytes = literal.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'\\' || i + 1 >= bytes.len() {
+            unescaped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        i += 1;
+        match bytes[i] {
+            b'n' => unescaped.push(b'\n'),
+            b't' => unescaped.push(b'\t'),
+            b'r' => unescaped.push(b'\r'),
+            b'a' => unescaped.push(0x07),
+            b'b' => unescaped.push(0x08),
+            b'f' => unescaped.push(0x0c),
+            b'v' => unescaped.push(0x0b),
+            b'z' => {
+                while bytes.get(i + 1).is_some_and(|byte| byte.is_ascii_whitespace()) {
+                    i += 1;
+                }
+            }
+            b'x' => {
+                let hex = literal.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(byte) = hex {
+                    unescaped.push(byte);
+                    i += 2;
+                }
+            }
+            b'u' => {
+                let code_point = literal
+                    .get(i + 1..)
+                    .filter(|rest| rest.starts_with('{'))
+                    .and_then(|rest| rest.find('}').map(|end| &rest[1..end]))
+                    .and_then(|hex| {
+                        let c = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?;
+                        Some((c, hex.len() + 2))
+                    });
+                if let Some((c, len)) = code_point {
+                    unescaped.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    i += len;
+                }
+            }
+            b'0'..=b'9' => {
+                let digits = bytes[i..]
+                    .iter()
+                    .take(3)
+                    .take_while(|byte| byte.is_ascii_digit())
+                    .count();
+                let value: u32 = literal[i..i + digits].parse().unwrap_or(0);
+                unescaped.push(u8::try_from(value).unwrap_or(u8::MAX));
+                i += digits - 1;
+            }
+            other => unescaped.push(other),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(segments: &[&str]) -> RobloxPath {
+        RobloxPath(segments.iter().map(|segment| segment.to_string()).collect())
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        let values = [
+            "plain",
+            "say \"hi\"",
+            "back\\slash",
+            "tab\tnew\nline\r",
+            "\u{1}0 control before a digit",
+            "héllo ☃ 😀",
+        ];
+        for value in values {
+            assert_eq!(unescape_string(&escape_string(value)), value);
+        }
+    }
+
+    #[test]
+    fn unescape_sequences() {
+        assert_eq!(unescape_string("\\65\\066\\0671"), "ABC1");
+        assert_eq!(unescape_string("\\x41\\u{48}\\u{1F600}"), "AH😀");
+        assert_eq!(unescape_string("\\'\\\"\\\\"), "'\"\\");
+        assert_eq!(unescape_string("a\\z  \n  b"), "ab");
+    }
+
+    #[test]
+    fn between_siblings_and_children() {
+        let source = path(&["game", "Shared", "a", "main"]);
+
+        let sibling = RequirePath::between(&source, &path(&["game", "Shared", "b", "util"]));
+        assert_eq!(sibling.parents, 2);
+        assert_eq!(sibling.children, ["b", "util"]);
+        assert_eq!(sibling.to_string(), "script.Parent.Parent.b.util");
+
+        let child = RequirePath::between(&source, &path(&["game", "Shared", "a", "main", "child"]));
+        assert_eq!(child.to_string(), "script.child");
+    }
+
+    #[test]
+    fn display_quotes_names_that_are_not_identifiers() {
+        let source = path(&["game", "main"]);
+        let target = path(&["game", "my.module", "end"]);
+        assert_eq!(
+            RequirePath::between(&source, &target).to_string(),
+            "script.Parent[\"my.module\"][\"end\"]"
+        );
+    }
+
+    #[test]
+    fn resolve_from_inverts_between() {
+        let source = path(&["game", "Shared", "a", "main"]);
+        for target in [
+            path(&["game", "Shared", "b", "util"]),
+            path(&["game", "Shared", "a", "main", "child"]),
+            path(&["game", "Other"]),
+        ] {
+            assert_eq!(RequirePath::between(&source, &target).resolve_from(&source), Some(target));
+        }
+    }
+
+    #[test]
+    fn resolve_from_rejects_paths_above_the_root() {
+        let source = path(&["game", "main"]);
+        let require_path = RequirePath {
+            root: "script".to_string(),
+            service: None,
+            parents: 2,
+            children: vec!["x".to_string()],
+        };
+        assert_eq!(require_path.resolve_from(&source), None);
+    }
+
+    #[test]
+    fn resolve_from_service() {
+        let target = path(&["game", "ReplicatedStorage", "Shared", "util"]);
+        let require_path = RequirePath::from_service(&target, "ReplicatedStorage").unwrap();
+        assert_eq!(
+            require_path.to_string(),
+            "game:GetService(\"ReplicatedStorage\").Shared.util"
+        );
+        let source = path(&["game", "ServerScriptService", "main"]);
+        assert_eq!(require_path.resolve_from(&source), Some(target));
+    }
+
+    #[test]
+    fn service_is_found_by_class_and_resolved_by_name() {
+        let target = path(&["game", "RS", "util"]);
+        let require_path = RequirePath::from_service(&target, "ReplicatedStorage").unwrap();
+        assert_eq!(require_path.to_string(), "game:GetService(\"ReplicatedStorage\").util");
+        assert_eq!(require_path.resolve_from(&path(&["game", "main"])), Some(target));
+    }
+
+    #[test]
+    fn from_service_rejects_other_classes() {
+        assert_eq!(RequirePath::from_service(&path(&["game", "Shared", "util"]), "Folder"), None);
+    }
+}
@@ -0,0 +1,184 @@
+use serde::Deserialize;
+use std::{ collections::BTreeMap, fs, path::{ Component, Path, PathBuf } };
+
+use crate::diagnostics::{ Code, Diagnostic };
+
+const LUAU_CONFIG_FILE_NAME: &str = ".luaurc";
+
+#[derive(Deserialize, Debug, Default)]
+struct LuauConfig {
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+/// An `@alias` from a `.luaurc`, pointing at a directory.
+#[derive(Debug, Clone)]
+pub struct Alias {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+/// Aliases visible from files in `dir`, keyed by lower-case name since Luau matches them without
+/// regard to case. Every `.luaurc` from `dir` up to the root contributes, and nearer ones win.
+pub fn load_aliases(
+    dir: &Path,
+    diagnostics: &mut Vec<Diagnostic>
+) -> BTreeMap<String, Alias> {
+    let mut aliases = BTreeMap::new();
+    for config_dir in dir.ancestors() {
+        let config_path = config_dir.join(LUAU_CONFIG_FILE_NAME);
+        let Ok(content) = fs::read_to_string(&config_path) else {
+            continue;
+        };
+        let config: LuauConfig = match serde_json::from_str(&content) {
+            Ok(config) => config,
+            Err(err) => {
+                diagnostics.push(
+                    Diagnostic::warning(Code::FileRead, format!("invalid .luaurc: {}", err))
+                        .with_file(&config_path)
+                        .with_note("its aliases are not used in string requires")
+                );
+                continue;
+            }
+        };
+        for (name, path) in config.aliases {
+            let alias_dir = config_dir.join(path);
+            let alias = Alias {
+                name: name.clone(),
+                dir: dunce::canonicalize(&alias_dir).unwrap_or_else(|_| normalize(&alias_dir)),
+            };
+            aliases.entry(name.to_lowercase()).or_insert(alias);
+        }
+    }
+    aliases
+}
+
+/// The string `importer` passes to `require` to load `target`, both being source files. Targets
+/// inside an aliased directory the importer is not part of are required through the alias,
+/// everything else relative to the importer. `None` if a name on the way is not valid UTF-8.
+pub fn string_require_path(
+    importer: &Path,
+    target: &Path,
+    aliases: &BTreeMap<String, Alias>
+) -> Option<String> {
+    let importer = module_path(importer);
+    let target = module_path(target);
+
+    let alias = aliases
+        .values()
+        .filter(|alias| target.starts_with(&alias.dir) && !importer.starts_with(&alias.dir))
+        .max_by_key(|alias| alias.dir.components().count());
+    if let Some(alias) = alias {
+        return join_components(format!("@{}", alias.name), target.strip_prefix(&alias.dir).ok()?);
+    }
+
+    // A directory module's own children are reached through `@self`, since `./` starts next to
+    // the module just like `script.Parent` does
+    if let Ok(inside) = target.strip_prefix(&importer) {
+        return join_components("@self".to_string(), inside);
+    }
+
+    // Luau has no bare `.`, so a directory module required from inside is named from its parent
+    let mut base = importer.parent()?;
+    let mut prefix = ".".to_string();
+    while !target.starts_with(base) || target == base {
+        base = base.parent()?;
+        prefix = if prefix == "." { "..".to_string() } else { format!("{}/..", prefix) };
+    }
+    join_components(prefix, target.strip_prefix(base).ok()?)
+}
+
+/// What a string require names for `file`: the directory of an `init` file, otherwise the file
+/// without its extension.
+fn module_path(file: &Path) -> PathBuf {
+    if file.file_stem().is_some_and(|stem| stem == "init") {
+        file.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else {
+        file.with_extension("")
+    }
+}
+
+fn join_components(mut joined: String, relative: &Path) -> Option<String> {
+    for component in relative.components() {
+        joined.push('/');
+        joined.push_str(component.as_os_str().to_str()?);
+    }
+    Some(joined)
+}
+
+/// Resolves `.` and `..` without touching the file system, for aliases pointing at directories
+/// that do not exist yet.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn require_path(importer: &str, target: &str, aliases: &[(&str, &str)]) -> Option<String> {
+        let aliases = aliases
+            .iter()
+            .map(|(name, dir)| {
+                (name.to_lowercase(), Alias { name: name.to_string(), dir: PathBuf::from(dir) })
+            })
+            .collect();
+        string_require_path(Path::new(importer), Path::new(target), &aliases)
+    }
+
+    #[test]
+    fn sibling() {
+        assert_eq!(
+            require_path("/p/src/a.luau", "/p/src/b.luau", &[]).as_deref(),
+            Some("./b")
+        );
+        assert_eq!(
+            require_path("/p/src/a/x.luau", "/p/src/b/y.luau", &[]).as_deref(),
+            Some("../b/y")
+        );
+    }
+
+    #[test]
+    fn child_of_directory_module() {
+        assert_eq!(
+            require_path("/p/src/pkg/init.luau", "/p/src/pkg/child.luau", &[]).as_deref(),
+            Some("@self/child")
+        );
+    }
+
+    #[test]
+    fn directory_module_from_its_child() {
+        assert_eq!(
+            require_path("/p/src/pkg/child.luau", "/p/src/pkg/init.luau", &[]).as_deref(),
+            Some("../pkg")
+        );
+        assert_eq!(
+            require_path("/p/src/pkg/sub/child.luau", "/p/src/pkg/init.luau", &[]).as_deref(),
+            Some("../../pkg")
+        );
+    }
+
+    #[test]
+    fn alias() {
+        let aliases = [("Shared", "/p/shared")];
+        assert_eq!(
+            require_path("/p/src/a.luau", "/p/shared/util/init.luau", &aliases).as_deref(),
+            Some("@Shared/util")
+        );
+        // Files inside the aliased directory keep requiring each other relatively
+        assert_eq!(
+            require_path("/p/shared/a.luau", "/p/shared/util/init.luau", &aliases).as_deref(),
+            Some("./util")
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use notify::{
+    event::ModifyKind,
+    Config,
+    Event,
+    EventKind,
+    PollWatcher,
+    RecommendedWatcher,
+    RecursiveMode,
+    Watcher,
+};
+use std::{ collections::BTreeSet, fs, path::{ Path, PathBuf }, sync::mpsc, time::Duration };
+
+use crate::{
+    cache::Cache,
+    collect_tasks,
+    config::{ DiagnosticsLevel, Settings },
+    diagnostics::{ Code, Diagnostic, Emitter },
+    is_project_file,
+    load_sourcemap,
+    process_tasks,
+    save_cache,
+    FileKind,
+    Inputs,
+    Report,
+    Run,
+    SourcemapData,
+};
+
+/// How long the file system has to stay quiet before a batch of changes is processed, so a
+/// `rbxtsc -w` rebuild touching many files results in a single pass.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+type EventSender = mpsc::Sender<notify::Result<Event>>;
+
+fn start_watcher(
+    inputs: &Inputs,
+    sender: EventSender,
+    poll: bool
+) -> notify::Result<Box<dyn Watcher>> {
+    let mut watcher: Box<dyn Watcher> = if poll {
+        Box::new(PollWatcher::new(sender, Config::default().with_poll_interval(POLL_INTERVAL))?)
+    } else {
+        Box::new(RecommendedWatcher::new(sender, Config::default())?)
+    };
+
+    watcher.watch(&inputs.transform_path, RecursiveMode::Recursive)?;
+
+    // Watch the directory rather than the file, since tools usually replace the sourcemap instead
+    // of writing to it, which would end a watch placed on the file itself
+    let mut watched_dirs = BTreeSet::new();
+    for sourcemap_path in sourcemap_paths(inputs) {
+        if let Some(sourcemap_dir) = sourcemap_path.parent()
+            && !sourcemap_dir.starts_with(&inputs.transform_path)
+            && watched_dirs.insert(sourcemap_dir)
+        {
+            watcher.watch(sourcemap_dir, RecursiveMode::NonRecursive)?;
+        }
+    }
+
+    Ok(watcher)
+}
+
+/// The main sourcemap followed by every sourcemap merged into it.
+fn sourcemap_paths(inputs: &Inputs) -> impl Iterator<Item = &Path> {
+    std::iter
+        ::once(inputs.sourcemap_path.as_path())
+        .chain(inputs.extra_sourcemaps.iter().map(|extra| extra.path.as_path()))
+}
+
+/// Whether editing `path` changes the tree read from a project file.
+fn affects_project(path: &Path) -> bool {
+    let file_kind = FileKind::of(&path.to_string_lossy());
+    matches!(file_kind, Some(FileKind::Project | FileKind::Meta))
+}
+
+/// Keeps re-running the transformer on changed files until the process is killed. A change to
+/// the sourcemap rebuilds it and re-checks every file against the cache, so only files whose
+/// imports now resolve differently are transformed again. The `--report` file is only written by
+/// the initial run.
+pub fn watch(settings: &Settings, inputs: &Inputs, mut maps: SourcemapData, mut cache: Cache) -> ! {
+    let (sender, receiver) = mpsc::channel();
+
+    // Native watchers can be unavailable (e.g. inotify limits or network file systems), in which
+    // case polling still works everywhere
+    let watcher = start_watcher(inputs, sender.clone(), settings.poll).or_else(|err| {
+        if !settings.poll && settings.diagnostics_level >= DiagnosticsLevel::Warning {
+            eprintln!("Native file watching unavailable ({}), falling back to polling.", err);
+        }
+        start_watcher(inputs, sender, true)
+    });
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            let mut emitter = Emitter::new(settings.diagnostics_format, settings.diagnostics_level);
+            emitter.emit(
+                Diagnostic::error(Code::InvalidInput, format!("cannot watch for changes: {}", err))
+                    .with_file(&inputs.transform_path)
+            );
+            std::process::exit(emitter.finish());
+        }
+    };
+
+    let cache_path = settings.cache.as_ref().filter(|_| !settings.dry_run && !settings.check);
+    let is_ignored = |path: &PathBuf| {
+        inputs.out_path.as_ref().is_some_and(|out| path.starts_with(out)) ||
+            cache_path.is_some_and(|cache| path.ends_with(cache)) ||
+            settings.report.as_ref().is_some_and(|report| path.ends_with(report))
+    };
+
+    let reads_project = sourcemap_paths(inputs).any(is_project_file);
+
+    if settings.diagnostics_level >= DiagnosticsLevel::Note {
+        eprintln!("Watching {} for changes...", inputs.transform_path.display());
+    }
+
+    while let Ok(first_event) = receiver.recv() {
+        let mut events = vec![first_event];
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            events.push(event);
+        }
+
+        let mut emitter = Emitter::new(settings.diagnostics_format, settings.diagnostics_level);
+        let mut changed = BTreeSet::new();
+        let mut sourcemap_changed = false;
+        for event in events {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    emitter.emit(Diagnostic::warning(Code::FileRead, err.to_string()));
+                    continue;
+                }
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            // A tree read from a project file follows the file system, so adding, removing or
+            // renaming files changes it just like editing a nested project does
+            let reshapes_project =
+                reads_project &&
+                matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+                );
+            for path in event.paths {
+                if sourcemap_paths(inputs).any(|sourcemap_path| path == sourcemap_path) {
+                    sourcemap_changed = true;
+                } else if is_ignored(&path) {
+                    continue;
+                } else if reads_project && (reshapes_project || affects_project(&path)) {
+                    sourcemap_changed = true;
+                } else if path.starts_with(&inputs.transform_path) {
+                    changed.insert(path);
+                }
+            }
+        }
+
+        if !sourcemap_changed && changed.is_empty() {
+            continue;
+        }
+
+        if sourcemap_changed {
+            match load_sourcemap(settings, inputs, &mut emitter) {
+                Some(new_maps) => {
+                    maps = new_maps;
+                }
+                // Keep transforming against the last good sourcemap until it is fixed
+                None => {
+                    emitter.finish();
+                    continue;
+                }
+            }
+        }
+
+        let mut tasks = Vec::new();
+        if sourcemap_changed {
+            tasks = collect_tasks(&inputs.transform_path, inputs, &mut emitter);
+        } else {
+            for path in &changed {
+                if path.exists() {
+                    tasks.extend(collect_tasks(path, inputs, &mut emitter));
+                    continue;
+                }
+
+                // Deleted sources take their mirrored output with them
+                let relative_path = path.strip_prefix(&inputs.transform_path).unwrap_or(path);
+                cache.files.remove(&relative_path.to_string_lossy().replace('\\', "/"));
+                if let Some(output_path) = inputs.out_path.as_ref().map(|out| out.join(relative_path)) {
+                    let _ = fs::remove_file(&output_path).or_else(|_| fs::remove_dir_all(&output_path));
+                }
+            }
+            tasks.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+            tasks.dedup_by(|a, b| a.relative_path == b.relative_path);
+        }
+
+        let run = Run {
+            settings,
+            sourcemap_data: &maps,
+            runtime_path: &inputs.runtime_path,
+            dry_run: settings.dry_run,
+            check: settings.check,
+            cache: Some(&cache),
+        };
+        let mut next_cache = cache.clone();
+        process_tasks(&run, &tasks, &mut emitter, &mut Report { rewrites: Vec::new() }, &mut next_cache);
+        cache = next_cache;
+
+        if let Some(cache_path) = cache_path {
+            save_cache(&cache, cache_path, &mut emitter);
+        }
+        emitter.finish();
+    }
+
+    std::process::exit(1);
+}